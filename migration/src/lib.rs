@@ -1,12 +1,36 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20220101_000001_create_table;
+mod m20251017_000002_add_rooms_users_role;
+mod m20251017_000003_add_rooms_private_room_key;
+mod m20251017_000004_add_messages_edited_at;
+mod m20251017_000005_add_messages_deleted_at;
+mod m20251017_000006_add_messages_reply_to;
+mod m20251017_000007_create_message_reactions_table;
+mod m20251017_000008_replace_messages_read_with_cursor;
+mod m20251017_000009_add_messages_room_id_index;
+mod m20251017_000010_add_users_last_seen_at;
+mod m20251017_000011_create_sessions_table;
+mod m20251017_000012_add_sessions_previous_refresh_token_hash;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_table::Migration),
+            Box::new(m20251017_000002_add_rooms_users_role::Migration),
+            Box::new(m20251017_000003_add_rooms_private_room_key::Migration),
+            Box::new(m20251017_000004_add_messages_edited_at::Migration),
+            Box::new(m20251017_000005_add_messages_deleted_at::Migration),
+            Box::new(m20251017_000006_add_messages_reply_to::Migration),
+            Box::new(m20251017_000007_create_message_reactions_table::Migration),
+            Box::new(m20251017_000008_replace_messages_read_with_cursor::Migration),
+            Box::new(m20251017_000009_add_messages_room_id_index::Migration),
+            Box::new(m20251017_000010_add_users_last_seen_at::Migration),
+            Box::new(m20251017_000011_create_sessions_table::Migration),
+            Box::new(m20251017_000012_add_sessions_previous_refresh_token_hash::Migration),
+        ]
     }
 }
@@ -0,0 +1,43 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomsUsers::Table)
+                    .add_column(string(RoomsUsers::Role).default("member"))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Before roles every member could manage the room, keep it that way for existing rooms
+        manager
+            .get_connection()
+            .execute_unprepared("UPDATE rooms_users SET role = 'owner'")
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomsUsers::Table)
+                    .drop_column(RoomsUsers::Role)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RoomsUsers {
+    Table,
+    Role,
+}
@@ -0,0 +1,84 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rooms::Table)
+                    .add_column(string_null(Rooms::PrivateRoomKey))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Private rooms are the ones with a generated name for exactly two members,
+        // only the oldest room of every pair keeps the key
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE rooms
+                SET private_room_key = (
+                    SELECT CAST(MIN(ru.user_id) AS TEXT) || ':' || CAST(MAX(ru.user_id) AS TEXT)
+                    FROM rooms_users ru
+                    WHERE ru.room_id = rooms.id
+                )
+                WHERE id IN (
+                    SELECT MIN(room_id)
+                    FROM (
+                        SELECT
+                            ru.room_id as room_id,
+                            CAST(MIN(ru.user_id) AS TEXT) || ':' || CAST(MAX(ru.user_id) AS TEXT) as pair_key
+                        FROM rooms_users ru
+                        WHERE ru.generated_room_name IS NOT NULL
+                        GROUP BY ru.room_id
+                        HAVING COUNT(*) = 2
+                    )
+                    GROUP BY pair_key
+                )
+                "#,
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-unique-room-private-room-key")
+                    .table(Rooms::Table)
+                    .col(Rooms::PrivateRoomKey)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-unique-room-private-room-key")
+                    .table(Rooms::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Rooms::Table)
+                    .drop_column(Rooms::PrivateRoomKey)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Rooms {
+    Table,
+    PrivateRoomKey,
+}
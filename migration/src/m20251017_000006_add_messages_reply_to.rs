@@ -0,0 +1,124 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite can't add a foreign key through ALTER TABLE builder, only inline in column definition
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                ALTER TABLE "messages"
+                ADD COLUMN "reply_to" uuid_text NULL
+                REFERENCES "messages" ("uuid") ON DELETE SET NULL
+                "#,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Sqlite refuses to drop a column used in a foreign key, so the table is rebuilt without it
+        manager
+            .create_table(
+                Table::create()
+                    .table(MessagesWithoutReply::Table)
+                    .col(pk_auto(Messages::Id))
+                    .col(uuid(Messages::Uuid))
+                    .col(date_time(Messages::CreatedAt))
+                    .col(string(Messages::Content))
+                    .col(integer(Messages::UserId))
+                    .col(integer(Messages::RoomId))
+                    .col(boolean(Messages::Read))
+                    .col(date_time_null(Messages::EditedAt))
+                    .col(date_time_null(Messages::DeletedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-messages-user_id")
+                            .from(MessagesWithoutReply::Table, Messages::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-messages-room_id")
+                            .from(MessagesWithoutReply::Table, Messages::RoomId)
+                            .to(Rooms::Table, Rooms::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        let connection = manager.get_connection();
+
+        connection
+            .execute_unprepared(
+                r#"
+                INSERT INTO "messages_without_reply"
+                    ("id", "uuid", "created_at", "content", "user_id", "room_id", "read", "edited_at", "deleted_at")
+                SELECT "id", "uuid", "created_at", "content", "user_id", "room_id", "read", "edited_at", "deleted_at"
+                FROM "messages"
+                "#,
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(Messages::Table).to_owned())
+            .await?;
+
+        manager
+            .rename_table(
+                Table::rename()
+                    .table(MessagesWithoutReply::Table, Messages::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-unique-message-uuid")
+                    .table(Messages::Table)
+                    .col(Messages::Uuid)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum MessagesWithoutReply {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Id,
+    Uuid,
+    UserId,
+    RoomId,
+    Read,
+    Content,
+    CreatedAt,
+    EditedAt,
+    DeletedAt,
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Rooms {
+    Table,
+    Id,
+}
@@ -0,0 +1,92 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomsUsers::Table)
+                    .add_column(integer_null(RoomsUsers::LastReadMessageId))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Flag was set by the recipient, so it becomes the read cursor of everyone except the author
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE rooms_users
+                SET last_read_message_id = (
+                    SELECT MAX(m.id)
+                    FROM messages m
+                    WHERE m.room_id = rooms_users.room_id
+                        AND m.user_id != rooms_users.user_id
+                        AND m.read = 1
+                )
+                "#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .drop_column(Messages::Read)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Messages::Table)
+                    .add_column(boolean(Messages::Read).default(false))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .get_connection()
+            .execute_unprepared(
+                r#"
+                UPDATE messages
+                SET read = EXISTS (
+                    SELECT 1
+                    FROM rooms_users ru
+                    WHERE ru.room_id = messages.room_id
+                        AND ru.user_id != messages.user_id
+                        AND ru.last_read_message_id >= messages.id
+                )
+                "#,
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(RoomsUsers::Table)
+                    .drop_column(RoomsUsers::LastReadMessageId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RoomsUsers {
+    Table,
+    LastReadMessageId,
+}
+
+#[derive(DeriveIden)]
+enum Messages {
+    Table,
+    Read,
+}
@@ -2,56 +2,350 @@ use axum::{
     extract::FromRequestParts,
     http::{header, request},
 };
+use cookie::{Cookie, SameSite};
 use nultr_shared_lib::request::AuthError;
 use rust_api_kit::http::client::Response;
 
-use crate::state;
+use crate::{config, state};
 
 use super::jwt;
 
+/// Browsers cannot set authorization header on websocket handshake, so token can be passed
+/// as `bearer.<token>` subprotocol, cookie or one-time ticket in `ticket` query parameter
+const WS_TOKEN_PROTOCOL_PREFIX: &str = "bearer.";
+const WS_TOKEN_COOKIE: &str = "nultr_token";
+const WS_TICKET_QUERY_PARAM: &str = "ticket";
+const WS_PATH: &str = "/ws";
+
+type AuthRejection = Response<(), (), AuthError>;
+
+#[derive(Debug, PartialEq)]
+enum TokenSource {
+    Bearer(String),
+    WsToken(String),
+    WsTicket(String),
+}
+
 impl FromRequestParts<state::ServiceState> for jwt::Claims {
-    type Rejection = Response<(), (), AuthError>;
+    type Rejection = AuthRejection;
 
     async fn from_request_parts(
         parts: &mut request::Parts,
         state: &state::ServiceState,
     ) -> Result<Self, Self::Rejection> {
-        let auth_header = parts
-            .headers
-            .get(header::AUTHORIZATION)
-            .ok_or({
-                tracing::error!("Missing auth header");
+        let token_data = match token_source(parts)? {
+            TokenSource::Bearer(token) => decode_token(state, token)?,
+            TokenSource::WsToken(token) => {
+                // Cookie is sent by browser on handshake from any page, so other sites must
+                // not be able to open socket on behalf of the user
+                if !is_allowed_origin(parts, &config::WS_ALLOWED_ORIGINS) {
+                    tracing::error!("Websocket origin is not allowed: {:?}", origin(parts));
 
-                Response::UnexpectedError(AuthError::InvalidToken)
-            })?
-            .to_str()
-            .map_err(|err| {
-                tracing::error!("Cannot convert auth header to str {err}");
+                    return Err(Response::UnexpectedError(AuthError::InvalidToken));
+                }
 
-                Response::UnexpectedError(AuthError::InvalidToken)
-            })?
-            .trim_start_matches(|c: char| c.is_whitespace() || c.is_control());
+                decode_token(state, token)?
+            }
+            TokenSource::WsTicket(ticket) => {
+                state.ws_ticket_store.redeem(&ticket).ok_or_else(|| {
+                    tracing::error!("Websocket ticket is unknown or expired");
 
-        let token = auth_header
-            .strip_prefix("Bearer ")
-            .or_else(|| auth_header.strip_prefix("bearer "))
-            .ok_or({
-                tracing::error!("Cannot strip bearer prefix on {auth_header}");
+                    Response::UnexpectedError(AuthError::InvalidToken)
+                })?
+            }
+        };
+
+        // Logout and session revocation delete the session, so its tokens are rejected before
+        // they expire, tickets included
+        let is_session_active = state
+            .session_repository
+            .is_active(token_data.session_id)
+            .await
+            .map_err(|err| {
+                tracing::error!("Session check failed: {err}");
 
                 Response::UnexpectedError(AuthError::InvalidToken)
             })?;
 
-        let token_data = state
-            .jwt_encoder
-            .decode(token.to_string())
-            .inspect_err(|err| {
-                tracing::error!(
-                    "Bearer decode failed for token: {token}, with error: {:?}",
-                    err
-                )
-            })
-            .map_err(|_| Response::UnexpectedError(AuthError::InvalidToken))?;
+        if !is_session_active {
+            tracing::error!("Session is revoked or expired: {}", token_data.session_id);
+
+            return Err(Response::UnexpectedError(AuthError::InvalidToken));
+        }
 
         Ok(token_data)
     }
 }
+
+/// Set-Cookie value with access token for websocket handshake, scripts can't read it and
+/// browser sends it only to websocket endpoint from the same site
+pub fn ws_token_cookie(token: &str) -> String {
+    Cookie::build((WS_TOKEN_COOKIE, token))
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .path(WS_PATH)
+        .build()
+        .to_string()
+}
+
+/// Other token sources are accepted only on websocket upgrade, so cookie can't be used for
+/// requests to http api
+fn token_source(parts: &request::Parts) -> Result<TokenSource, AuthRejection> {
+    let is_websocket_upgrade = is_websocket_upgrade(parts);
+    let has_auth_header = parts.headers.contains_key(header::AUTHORIZATION);
+
+    if !is_websocket_upgrade || has_auth_header {
+        return bearer_token(parts).map(TokenSource::Bearer);
+    }
+
+    if let Some(ticket) = ws_ticket(parts) {
+        return Ok(TokenSource::WsTicket(ticket));
+    }
+
+    ws_token(parts).map(TokenSource::WsToken)
+}
+
+fn decode_token(state: &state::ServiceState, token: String) -> Result<jwt::Claims, AuthRejection> {
+    state.jwt_encoder.decode(token).map_err(|err| {
+        tracing::error!("Token decode failed with error: {:?}", err);
+
+        Response::UnexpectedError(AuthError::InvalidToken)
+    })
+}
+
+/// Origin has to match the host of the request or be listed in comma separated allowlist,
+/// clients without origin are not browsers and can use authorization header instead
+fn is_allowed_origin(parts: &request::Parts, allowed_origins: &str) -> bool {
+    let Some(origin) = origin(parts) else {
+        return false;
+    };
+
+    let is_same_host = origin
+        .split_once("://")
+        .map(|(_, authority)| authority)
+        .zip(parts.headers.get(header::HOST))
+        .is_some_and(|(authority, host)| host.as_bytes() == authority.as_bytes());
+
+    is_same_host
+        || allowed_origins
+            .split(',')
+            .map(str::trim)
+            .any(|allowed_origin| !allowed_origin.is_empty() && allowed_origin == origin)
+}
+
+fn origin(parts: &request::Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn bearer_token(parts: &request::Parts) -> Result<String, AuthRejection> {
+    let auth_header = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .ok_or({
+            tracing::error!("Missing auth header");
+
+            Response::UnexpectedError(AuthError::InvalidToken)
+        })?
+        .to_str()
+        .map_err(|err| {
+            tracing::error!("Cannot convert auth header to str {err}");
+
+            Response::UnexpectedError(AuthError::InvalidToken)
+        })?
+        .trim_start_matches(|c: char| c.is_whitespace() || c.is_control());
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .or_else(|| auth_header.strip_prefix("bearer "))
+        .ok_or({
+            tracing::error!("Cannot strip bearer prefix on {auth_header}");
+
+            Response::UnexpectedError(AuthError::InvalidToken)
+        })?;
+
+    Ok(token.to_string())
+}
+
+fn ws_token(parts: &request::Parts) -> Result<String, AuthRejection> {
+    let protocol_token = parts
+        .headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| protocol.trim().strip_prefix(WS_TOKEN_PROTOCOL_PREFIX))
+        .map(str::to_string);
+
+    let cookie_token = || {
+        parts
+            .headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(Cookie::split_parse)
+            .filter_map(Result::ok)
+            .find(|cookie| cookie.name() == WS_TOKEN_COOKIE)
+            .map(|cookie| cookie.value().to_string())
+    };
+
+    protocol_token.or_else(cookie_token).ok_or_else(|| {
+        tracing::error!("Missing websocket token");
+
+        Response::UnexpectedError(AuthError::InvalidToken)
+    })
+}
+
+fn ws_ticket(parts: &request::Parts) -> Option<String> {
+    let query = parts.uri.query()?;
+
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == WS_TICKET_QUERY_PARAM)
+        .map(|(_, ticket)| ticket.into_owned())
+}
+
+fn is_websocket_upgrade(parts: &request::Parts) -> bool {
+    parts
+        .headers
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+
+    use super::*;
+
+    fn parts(uri: &str, headers: &[(header::HeaderName, &str)]) -> request::Parts {
+        let mut builder = Request::builder().uri(uri);
+
+        for (name, value) in headers {
+            builder = builder.header(name, *value);
+        }
+
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    fn upgrade_parts(uri: &str, headers: &[(header::HeaderName, &str)]) -> request::Parts {
+        let mut headers = headers.to_vec();
+        headers.push((header::UPGRADE, "WebSocket"));
+
+        parts(uri, &headers)
+    }
+
+    #[test]
+    fn websocket_upgrade_is_detected_case_insensitively() {
+        assert!(is_websocket_upgrade(&upgrade_parts("/ws", &[])));
+        assert!(!is_websocket_upgrade(&parts("/ws", &[])));
+        assert!(!is_websocket_upgrade(&parts(
+            "/ws",
+            &[(header::UPGRADE, "h2c")]
+        )));
+    }
+
+    #[test]
+    fn ws_token_is_read_from_subprotocol_before_cookie() {
+        let parts = upgrade_parts(
+            "/ws",
+            &[
+                (header::SEC_WEBSOCKET_PROTOCOL, "nultr, bearer.token"),
+                (header::COOKIE, "other=value; nultr_token=cookie-token"),
+            ],
+        );
+
+        assert_eq!(ws_token(&parts).ok(), Some("token".to_string()));
+    }
+
+    #[test]
+    fn ws_token_is_read_from_cookie() {
+        let parts = upgrade_parts("/ws", &[(header::COOKIE, "other=value; nultr_token=token")]);
+
+        assert_eq!(ws_token(&parts).ok(), Some("token".to_string()));
+        assert!(ws_token(&upgrade_parts("/ws", &[])).is_err());
+    }
+
+    #[test]
+    fn ws_ticket_is_read_from_query() {
+        assert_eq!(
+            ws_ticket(&parts("/ws?other=1&ticket=abc", &[])),
+            Some("abc".to_string())
+        );
+        assert_eq!(ws_ticket(&parts("/ws?other=1", &[])), None);
+        assert_eq!(ws_ticket(&parts("/ws", &[])), None);
+    }
+
+    #[test]
+    fn cookie_and_ticket_are_ignored_on_plain_http() {
+        let cookie = (header::COOKIE, "nultr_token=token");
+
+        assert!(token_source(&parts("/api?ticket=abc", &[cookie.clone()])).is_err());
+        assert_eq!(
+            token_source(&parts(
+                "/api?ticket=abc",
+                &[cookie, (header::AUTHORIZATION, "Bearer header-token")]
+            ))
+            .ok(),
+            Some(TokenSource::Bearer("header-token".to_string()))
+        );
+    }
+
+    #[test]
+    fn websocket_token_source_prefers_header_then_ticket() {
+        let cookie = (header::COOKIE, "nultr_token=token");
+
+        assert_eq!(
+            token_source(&upgrade_parts("/ws", &[cookie.clone()])).ok(),
+            Some(TokenSource::WsToken("token".to_string()))
+        );
+        assert_eq!(
+            token_source(&upgrade_parts("/ws?ticket=abc", &[cookie.clone()])).ok(),
+            Some(TokenSource::WsTicket("abc".to_string()))
+        );
+        assert_eq!(
+            token_source(&upgrade_parts(
+                "/ws?ticket=abc",
+                &[cookie, (header::AUTHORIZATION, "Bearer header-token")]
+            ))
+            .ok(),
+            Some(TokenSource::Bearer("header-token".to_string()))
+        );
+    }
+
+    #[test]
+    fn origin_must_match_host_or_allowlist() {
+        let allowed_origins = "https://app.example.com, https://other.example.com";
+        let host_parts = |origin: &str| {
+            parts(
+                "/ws",
+                &[(header::HOST, "chat.example.com"), (header::ORIGIN, origin)],
+            )
+        };
+
+        let same_host = host_parts("https://chat.example.com");
+        let listed = host_parts("https://app.example.com");
+        let foreign = host_parts("https://evil.example.com");
+        let missing = parts("/ws", &[(header::HOST, "chat.example.com")]);
+
+        assert!(is_allowed_origin(&same_host, ""));
+        assert!(is_allowed_origin(&listed, allowed_origins));
+        assert!(!is_allowed_origin(&listed, ""));
+        assert!(!is_allowed_origin(&foreign, allowed_origins));
+        assert!(!is_allowed_origin(&missing, allowed_origins));
+    }
+
+    #[test]
+    fn ws_token_cookie_is_restricted() {
+        let cookie = ws_token_cookie("token");
+
+        assert!(cookie.starts_with("nultr_token=token"));
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Secure"));
+        assert!(cookie.contains("SameSite=Strict"));
+        assert!(cookie.contains("Path=/ws"));
+    }
+}
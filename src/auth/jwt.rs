@@ -3,15 +3,29 @@ use jsonwebtoken::{DecodingKey, Validation, decode, errors::Error as JwtError};
 use jsonwebtoken::{EncodingKey, Header, encode};
 use serde::{Deserialize, Serialize};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 use crate::config;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub user_id: i32,
+    pub session_id: Uuid,
+    pub jti: Uuid,
     pub exp: usize,
 }
 
+impl Claims {
+    pub fn expires_in(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        Duration::from_secs((self.exp as u64).saturating_sub(now))
+    }
+}
+
 #[derive(Clone)]
 pub struct Encoder {
     secret: String,
@@ -19,13 +33,15 @@ pub struct Encoder {
 
 impl Default for Encoder {
     fn default() -> Self {
-        Self {
-            secret: config::JWT_SECRET_KEY.clone(),
-        }
+        Self::new(config::JWT_SECRET_KEY.clone())
     }
 }
 impl Encoder {
-    pub fn encode(&self, user_id: i32) -> Result<String, anyhow::Error> {
+    pub fn new(secret: String) -> Self {
+        Self { secret }
+    }
+
+    pub fn encode(&self, user_id: i32, session_id: Uuid) -> Result<String, anyhow::Error> {
         let expiration = SystemTime::now()
             .checked_add(Duration::from_secs(60 * 60))
             .unwrap()
@@ -35,6 +51,8 @@ impl Encoder {
 
         let claims = Claims {
             user_id,
+            session_id,
+            jti: Uuid::new_v4(),
             exp: expiration,
         };
 
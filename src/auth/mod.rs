@@ -1,5 +1,7 @@
 pub mod http;
 pub mod jwt;
+pub mod refresh_token;
+pub mod ws_ticket;
 
 use argon2::password_hash::SaltString;
 use argon2::password_hash::rand_core::OsRng;
@@ -0,0 +1,43 @@
+use std::fmt;
+
+use rand::{Rng, distr::Alphanumeric};
+use uuid::Uuid;
+
+const SECRET_LENGTH: usize = 48;
+
+/// Refresh token is sent to client as `<session uuid>.<secret>`, only secret hash is stored
+pub struct RefreshToken {
+    pub session_uuid: Uuid,
+    pub secret: String,
+}
+
+impl RefreshToken {
+    pub fn generate(session_uuid: Uuid) -> Self {
+        let secret: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(SECRET_LENGTH)
+            .map(char::from)
+            .collect();
+
+        Self {
+            session_uuid,
+            secret,
+        }
+    }
+
+    pub fn parse(token: &str) -> Option<Self> {
+        let (session_uuid, secret) = token.split_once('.')?;
+        let session_uuid = Uuid::parse_str(session_uuid).ok()?;
+
+        Some(Self {
+            session_uuid,
+            secret: secret.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for RefreshToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.session_uuid, self.secret)
+    }
+}
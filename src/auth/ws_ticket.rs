@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use rand::{Rng, distr::Alphanumeric};
+
+use super::jwt::Claims;
+
+const TICKET_LENGTH: usize = 32;
+const TICKET_TTL: Duration = Duration::from_secs(30);
+
+/// One-time tickets for websocket handshake, browsers cannot send authorization header there
+pub struct TicketStore {
+    tickets: Mutex<HashMap<String, (Claims, Instant)>>,
+    ttl: Duration,
+}
+
+impl Default for TicketStore {
+    fn default() -> Self {
+        Self::new(TICKET_TTL)
+    }
+}
+
+impl TicketStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            tickets: Mutex::default(),
+            ttl,
+        }
+    }
+
+    pub fn issue(&self, claims: Claims) -> String {
+        let ticket: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(TICKET_LENGTH)
+            .map(char::from)
+            .collect();
+
+        let mut tickets = self.lock_tickets();
+        let now = Instant::now();
+
+        // Unused tickets are cleaned up here, so the store does not grow
+        tickets.retain(|_, (_, expires_at)| *expires_at > now);
+        tickets.insert(ticket.clone(), (claims, now + self.ttl));
+
+        ticket
+    }
+
+    pub fn redeem(&self, ticket: &str) -> Option<Claims> {
+        let (claims, expires_at) = self.lock_tickets().remove(ticket)?;
+
+        (expires_at > Instant::now()).then_some(claims)
+    }
+
+    fn lock_tickets(&self) -> MutexGuard<'_, HashMap<String, (Claims, Instant)>> {
+        self.tickets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::test_util;
+
+    use super::TicketStore;
+
+    #[test]
+    fn ticket_is_redeemed_only_once() {
+        let ticket_store = TicketStore::default();
+        let ticket = ticket_store.issue(test_util::claims(1));
+
+        let claims = ticket_store.redeem(&ticket).unwrap();
+
+        assert_eq!(claims.user_id, 1);
+        assert!(ticket_store.redeem(&ticket).is_none());
+    }
+
+    #[test]
+    fn expired_ticket_is_not_redeemed() {
+        let ticket_store = TicketStore::new(Duration::ZERO);
+        let ticket = ticket_store.issue(test_util::claims(1));
+
+        assert!(ticket_store.redeem(&ticket).is_none());
+    }
+
+    #[test]
+    fn unknown_ticket_is_not_redeemed() {
+        let ticket_store = TicketStore::default();
+        ticket_store.issue(test_util::claims(1));
+
+        assert!(ticket_store.redeem("unknown").is_none());
+    }
+}
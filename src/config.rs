@@ -16,6 +16,29 @@ macro_rules! env_lazy {
     };
 }
 
+macro_rules! env_lazy_or {
+    ($name:ident, $type:ty, $default:expr) => {
+        pub static $name: Lazy<$type> = Lazy::new(|| {
+            env::var(stringify!($name))
+                .map(|value| {
+                    value.parse::<$type>().expect(concat!(
+                        stringify!($name),
+                        " must be a valid ",
+                        stringify!($type)
+                    ))
+                })
+                .unwrap_or($default)
+        });
+    };
+}
+
 env_lazy!(DATABASE_URL, String);
 env_lazy!(WS_URL, String);
 env_lazy!(JWT_SECRET_KEY, String);
+env_lazy_or!(WS_EVENT_QUEUE_SIZE, usize, 256);
+env_lazy_or!(WS_PING_INTERVAL_SECS, u64, 30);
+env_lazy_or!(WS_IDLE_TIMEOUT_SECS, u64, 90);
+env_lazy_or!(WS_SEND_TIMEOUT_SECS, u64, 10);
+env_lazy_or!(WS_ALLOWED_ORIGINS, String, String::new());
+env_lazy_or!(REFRESH_TOKEN_TTL_DAYS, i64, 30);
+env_lazy_or!(SESSION_CLEANUP_INTERVAL_SECS, u64, 3600);
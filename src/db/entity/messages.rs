@@ -11,14 +11,18 @@ pub struct Model {
     #[sea_orm(unique)]
     pub uuid: Uuid,
     pub created_at: NaiveDateTime,
+    pub edited_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
     pub content: String,
-    pub read: bool,
     pub user_id: i32,
     pub room_id: i32,
+    pub reply_to: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::message_reactions::Entity")]
+    MessageReactions,
     #[sea_orm(
         belongs_to = "super::rooms::Entity",
         from = "Column::RoomId",
@@ -37,6 +41,12 @@ pub enum Relation {
     Users,
 }
 
+impl Related<super::message_reactions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::MessageReactions.def()
+    }
+}
+
 impl Related<super::rooms::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Rooms.def()
@@ -2,7 +2,10 @@
 
 pub mod prelude;
 
+pub mod message_reactions;
 pub mod messages;
 pub mod rooms;
 pub mod rooms_users;
+pub mod sea_orm_active_enums;
+pub mod sessions;
 pub mod users;
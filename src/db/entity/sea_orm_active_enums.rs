@@ -0,0 +1,50 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+pub enum RoomRole {
+    #[sea_orm(string_value = "owner")]
+    Owner,
+    #[sea_orm(string_value = "admin")]
+    Admin,
+    #[sea_orm(string_value = "member")]
+    Member,
+}
+
+impl RoomRole {
+    pub fn can_manage_room(&self) -> bool {
+        matches!(self, RoomRole::Owner | RoomRole::Admin)
+    }
+
+    pub fn outranks(&self, other: &RoomRole) -> bool {
+        self.rank() > other.rank()
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            RoomRole::Owner => 2,
+            RoomRole::Admin => 1,
+            RoomRole::Member => 0,
+        }
+    }
+}
+
+impl From<RoomRole> for nultr_shared_lib::request::RoomRole {
+    fn from(role: RoomRole) -> Self {
+        match role {
+            RoomRole::Owner => Self::Owner,
+            RoomRole::Admin => Self::Admin,
+            RoomRole::Member => Self::Member,
+        }
+    }
+}
+
+impl From<nultr_shared_lib::request::RoomRole> for RoomRole {
+    fn from(role: nultr_shared_lib::request::RoomRole) -> Self {
+        match role {
+            nultr_shared_lib::request::RoomRole::Owner => Self::Owner,
+            nultr_shared_lib::request::RoomRole::Admin => Self::Admin,
+            nultr_shared_lib::request::RoomRole::Member => Self::Member,
+        }
+    }
+}
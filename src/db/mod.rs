@@ -1,8 +1,8 @@
 use sea_orm::PrimaryKeyTrait;
 use sea_orm::prelude::async_trait::async_trait;
 use sea_orm::{
-    ActiveModelTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel,
-    TransactionTrait,
+    ActiveModelTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    IntoActiveModel, SqlErr, TransactionTrait,
 };
 use serde::Deserialize;
 use tokio::sync::OnceCell;
@@ -43,41 +43,91 @@ where
 {
     async fn exists_by_id(&self, id: Identifier) -> anyhow::Result<bool> {
         let connection = self.get_connection().await?;
+        self.exists_by_id_in(connection, id).await
+    }
+
+    async fn get_by_id(&self, id: Identifier) -> anyhow::Result<Option<E::Model>> {
+        let connection = self.get_connection().await?;
+        self.get_by_id_in(connection, id).await
+    }
+
+    async fn update(&self, model: E::ActiveModel) -> anyhow::Result<()> {
+        let connection = self.get_connection().await?;
+        self.update_in(connection, model).await
+    }
+
+    async fn insert(&self, active_model: E::ActiveModel) -> anyhow::Result<E::Model> {
+        let connection = self.get_connection().await?;
+        self.insert_in(connection, active_model).await
+    }
+
+    async fn delete(&self, model: E::ActiveModel) -> anyhow::Result<()> {
+        let connection = self.get_connection().await?;
+        self.delete_in(connection, model).await
+    }
+
+    async fn get_all(&self) -> anyhow::Result<Vec<E::Model>> {
+        let connection = self.get_connection().await?;
+        self.get_all_in(connection).await
+    }
+
+    async fn exists_by_id_in<C>(&self, connection: &C, id: Identifier) -> anyhow::Result<bool>
+    where
+        C: ConnectionTrait,
+    {
         let exists = E::find_by_id(id).one(connection).await?.is_some();
 
         Ok(exists)
     }
 
-    async fn get_by_id(&self, id: Identifier) -> anyhow::Result<Option<E::Model>> {
-        let connection = self.get_connection().await?;
+    async fn get_by_id_in<C>(
+        &self,
+        connection: &C,
+        id: Identifier,
+    ) -> anyhow::Result<Option<E::Model>>
+    where
+        C: ConnectionTrait,
+    {
         let user = E::find_by_id(id).one(connection).await?;
 
         Ok(user)
     }
 
-    async fn update(&self, model: E::ActiveModel) -> anyhow::Result<()> {
-        let connection = self.get_connection().await?;
+    async fn update_in<C>(&self, connection: &C, model: E::ActiveModel) -> anyhow::Result<()>
+    where
+        C: ConnectionTrait,
+    {
         model.update(connection).await?;
 
         Ok(())
     }
 
-    async fn insert(&self, active_model: E::ActiveModel) -> anyhow::Result<E::Model> {
-        let connection = self.get_connection().await?;
+    async fn insert_in<C>(
+        &self,
+        connection: &C,
+        active_model: E::ActiveModel,
+    ) -> anyhow::Result<E::Model>
+    where
+        C: ConnectionTrait,
+    {
         let model = active_model.insert(connection).await?;
 
         Ok(model)
     }
 
-    async fn delete(&self, model: E::ActiveModel) -> anyhow::Result<()> {
-        let connection = self.get_connection().await?;
+    async fn delete_in<C>(&self, connection: &C, model: E::ActiveModel) -> anyhow::Result<()>
+    where
+        C: ConnectionTrait,
+    {
         model.delete(connection).await?;
 
         Ok(())
     }
 
-    async fn get_all(&self) -> anyhow::Result<Vec<E::Model>> {
-        let connection = self.get_connection().await?;
+    async fn get_all_in<C>(&self, connection: &C) -> anyhow::Result<Vec<E::Model>>
+    where
+        C: ConnectionTrait,
+    {
         let models = E::find().all(connection).await?;
 
         Ok(models)
@@ -98,6 +148,32 @@ impl Default for LazyConnector {
     }
 }
 
+#[cfg(test)]
+impl LazyConnector {
+    /// Migrated in-memory database, every sqlite memory connection opens its own empty
+    /// database, so pool is limited to one connection
+    pub async fn in_memory() -> Self {
+        use migration::MigratorTrait;
+
+        let db_url = "sqlite::memory:".to_string();
+        let mut options = sea_orm::ConnectOptions::new(db_url.clone());
+        options.max_connections(1);
+
+        let db = sea_orm::Database::connect(options)
+            .await
+            .expect("In-memory database connection failed");
+
+        migration::Migrator::up(&db, None)
+            .await
+            .expect("In-memory database migration failed");
+
+        Self {
+            db_url,
+            db_pool: OnceCell::new_with(Some(db)),
+        }
+    }
+}
+
 impl LazyConnector {
     async fn get_connection(&self) -> anyhow::Result<&DatabaseConnection> {
         self.db_pool
@@ -110,6 +186,13 @@ impl LazyConnector {
     }
 }
 
+pub fn is_unique_violation(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<DbErr>().and_then(DbErr::sql_err),
+        Some(SqlErr::UniqueConstraintViolation(_))
+    )
+}
+
 #[derive(Deserialize)]
 pub struct Pagination {
     pub page: u64,
@@ -1,8 +1,11 @@
 use sea_orm::{DatabaseConnection, prelude::async_trait::async_trait};
 
-use crate::db::{entity::{messages, rooms, users}, DbConnectionContainerTrait, RepositoryTrait};
+use crate::db::{
+    DbConnectionContainerTrait, RepositoryTrait,
+    entity::{messages, rooms, sessions, users},
+};
 
-use super::{MessageRepository, RoomRepository, UserRepository};
+use super::{MessageRepository, RoomRepository, SessionRepository, UserRepository};
 
 #[async_trait]
 impl DbConnectionContainerTrait for UserRepository {
@@ -31,3 +34,11 @@ impl DbConnectionContainerTrait for MessageRepository {
 
 impl RepositoryTrait<messages::Entity> for MessageRepository {}
 
+#[async_trait]
+impl DbConnectionContainerTrait for SessionRepository {
+    async fn get_connection(&self) -> anyhow::Result<&DatabaseConnection> {
+        self.lazy_connector.get_connection().await
+    }
+}
+
+impl RepositoryTrait<sessions::Entity> for SessionRepository {}
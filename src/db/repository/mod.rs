@@ -2,13 +2,19 @@ pub mod implementation;
 
 use super::{
     DbConnectionContainerTrait, Identifier, LazyConnector, Pagination,
-    entity::{messages, rooms, rooms_users, users},
+    entity::{
+        message_reactions, messages, rooms, rooms_users, sea_orm_active_enums::RoomRole, sessions,
+        users,
+    },
 };
-use nultr_shared_lib::request::UuidIdentifier;
+use chrono::{NaiveDateTime, Utc};
+use nultr_shared_lib::request::{LastMessageResponse, RoomResponse, UuidIdentifier};
 use sea_orm::{
-    ColumnTrait, DbBackend, EntityTrait, FromQueryResult, JoinType,
+    ActiveValue::Set,
+    ColumnTrait, Condition, ConnectionTrait, DbBackend, EntityTrait, FromQueryResult, JoinType,
     PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Statement,
     prelude::Expr,
+    sea_query::{OnConflict, Query},
 };
 use std::sync::Arc;
 use uuid::Uuid;
@@ -26,6 +32,29 @@ impl UserRepository {
 
         Ok(user)
     }
+
+    pub async fn get_by_ids(&self, ids: Vec<Identifier>) -> anyhow::Result<Vec<users::Model>> {
+        let connection = self.get_connection().await?;
+        let filter = users::Column::Id.is_in(ids);
+        let users = users::Entity::find().filter(filter).all(connection).await?;
+
+        Ok(users)
+    }
+
+    pub async fn update_last_seen_at(
+        &self,
+        user_id: Identifier,
+        last_seen_at: NaiveDateTime,
+    ) -> anyhow::Result<()> {
+        let connection = self.get_connection().await?;
+        users::Entity::update_many()
+            .col_expr(users::Column::LastSeenAt, Expr::value(last_seen_at))
+            .filter(users::Column::Id.eq(user_id))
+            .exec(connection)
+            .await?;
+
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -36,7 +65,44 @@ pub struct RoomRepository {
 #[derive(Debug, FromQueryResult)]
 pub struct PersonalizedRoomData {
     pub id: Identifier,
+    pub user_id: Identifier,
     pub name: String,
+    pub role: RoomRole,
+    pub unread_count: i64,
+    pub last_message_uuid: Option<Uuid>,
+    pub last_message_user_id: Option<Identifier>,
+    pub last_message_content: Option<String>,
+    pub last_message_created_at: Option<NaiveDateTime>,
+}
+
+impl PersonalizedRoomData {
+    pub fn last_message(&self) -> Option<LastMessageResponse> {
+        Some(LastMessageResponse {
+            uuid: self.last_message_uuid?,
+            user_id: self.last_message_user_id?,
+            content: self.last_message_content.clone()?,
+            created_at: self.last_message_created_at?,
+        })
+    }
+}
+
+impl From<&PersonalizedRoomData> for RoomResponse {
+    fn from(room: &PersonalizedRoomData) -> Self {
+        Self {
+            id: room.id,
+            name: room.name.clone(),
+            role: room.role.into(),
+            unread_count: room.unread_count as u64,
+            last_message: room.last_message(),
+        }
+    }
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct RoomMemberData {
+    pub id: Identifier,
+    pub username: String,
+    pub role: RoomRole,
 }
 
 impl RoomRepository {
@@ -45,12 +111,7 @@ impl RoomRepository {
         user_id: Identifier,
     ) -> anyhow::Result<Vec<PersonalizedRoomData>> {
         let connection = self.get_connection().await?;
-        let query = r#"
-            SELECT r.id as id, COALESCE(ru.generated_room_name, r.name, '#' || CAST(r.id as TEXT)) as name
-            FROM rooms r
-            INNER JOIN rooms_users ru ON r.id = ru.room_id
-            WHERE ru.user_id = ?
-        "#;
+        let query = Self::personalized_rooms_query("ru.user_id = ?");
 
         let rooms: Vec<PersonalizedRoomData> = PersonalizedRoomData::find_by_statement(
             Statement::from_sql_and_values(DbBackend::Sqlite, query, vec![user_id.into()]),
@@ -61,11 +122,116 @@ impl RoomRepository {
         Ok(rooms)
     }
 
+    /// Same data as `get_for_user`, but for every member of one room
+    pub async fn get_for_room_members(
+        &self,
+        room_id: Identifier,
+    ) -> anyhow::Result<Vec<PersonalizedRoomData>> {
+        let connection = self.get_connection().await?;
+        let query = Self::personalized_rooms_query("ru.room_id = ?");
+
+        let rooms: Vec<PersonalizedRoomData> = PersonalizedRoomData::find_by_statement(
+            Statement::from_sql_and_values(DbBackend::Sqlite, query, vec![room_id.into()]),
+        )
+        .all(connection)
+        .await?;
+
+        Ok(rooms)
+    }
+
+    pub async fn get_for_room_member(
+        &self,
+        room_id: Identifier,
+        user_id: Identifier,
+    ) -> anyhow::Result<Option<PersonalizedRoomData>> {
+        let connection = self.get_connection().await?;
+        let query = Self::personalized_rooms_query("ru.room_id = ? AND ru.user_id = ?");
+
+        let values = vec![room_id.into(), user_id.into()];
+
+        let room: Option<PersonalizedRoomData> = PersonalizedRoomData::find_by_statement(
+            Statement::from_sql_and_values(DbBackend::Sqlite, query, values),
+        )
+        .one(connection)
+        .await?;
+
+        Ok(room)
+    }
+
+    fn personalized_rooms_query(filter: &str) -> String {
+        format!(
+            r#"
+            SELECT
+                r.id as id,
+                ru.user_id as user_id,
+                COALESCE(ru.generated_room_name, r.name, '#' || CAST(r.id as TEXT)) as name,
+                ru.role as role,
+                (
+                    SELECT COUNT(*)
+                    FROM messages m
+                    WHERE m.room_id = r.id
+                        AND m.id > COALESCE(ru.last_read_message_id, 0)
+                        AND m.user_id != ru.user_id
+                        AND m.deleted_at IS NULL
+                ) as unread_count,
+                lm.uuid as last_message_uuid,
+                lm.user_id as last_message_user_id,
+                SUBSTR(lm.content, 1, 100) as last_message_content,
+                lm.created_at as last_message_created_at
+            FROM rooms r
+            INNER JOIN rooms_users ru ON r.id = ru.room_id
+            LEFT JOIN messages lm ON lm.id = (
+                SELECT m.id
+                FROM messages m
+                WHERE m.room_id = r.id AND m.deleted_at IS NULL
+                ORDER BY m.id DESC
+                LIMIT 1
+            )
+            WHERE {filter}
+            ORDER BY lm.id IS NULL, lm.id DESC
+        "#
+        )
+    }
+
+    pub fn private_room_key(user_id: Identifier, other_user_id: Identifier) -> String {
+        let (first, second) = if user_id < other_user_id {
+            (user_id, other_user_id)
+        } else {
+            (other_user_id, user_id)
+        };
+
+        format!("{first}:{second}")
+    }
+
+    pub async fn get_private_room(
+        &self,
+        user_id: Identifier,
+        other_user_id: Identifier,
+    ) -> anyhow::Result<Option<rooms::Model>> {
+        let connection = self.get_connection().await?;
+        let filter =
+            rooms::Column::PrivateRoomKey.eq(Self::private_room_key(user_id, other_user_id));
+        let room = rooms::Entity::find().filter(filter).one(connection).await?;
+
+        Ok(room)
+    }
+
     pub async fn insert_rooms_users(
         &self,
         models: Vec<rooms_users::ActiveModel>,
     ) -> anyhow::Result<()> {
         let connection = self.get_connection().await?;
+        self.insert_rooms_users_in(connection, models).await
+    }
+
+    pub async fn insert_rooms_users_in<C>(
+        &self,
+        connection: &C,
+        models: Vec<rooms_users::ActiveModel>,
+    ) -> anyhow::Result<()>
+    where
+        C: ConnectionTrait,
+    {
         rooms_users::Entity::insert_many(models)
             .exec(connection)
             .await?;
@@ -73,6 +239,105 @@ impl RoomRepository {
         Ok(())
     }
 
+    pub async fn get_room_user(
+        &self,
+        room_id: Identifier,
+        user_id: Identifier,
+    ) -> anyhow::Result<Option<rooms_users::Model>> {
+        let connection = self.get_connection().await?;
+        let room_user = rooms_users::Entity::find_by_id((room_id, user_id))
+            .one(connection)
+            .await?;
+
+        Ok(room_user)
+    }
+
+    pub async fn update_room_user_role(
+        &self,
+        room_id: Identifier,
+        user_id: Identifier,
+        role: RoomRole,
+    ) -> anyhow::Result<()> {
+        let connection = self.get_connection().await?;
+        rooms_users::Entity::update_many()
+            .col_expr(rooms_users::Column::Role, Expr::value(role))
+            .filter(rooms_users::Column::RoomId.eq(room_id))
+            .filter(rooms_users::Column::UserId.eq(user_id))
+            .exec(connection)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_room_user(
+        &self,
+        room_id: Identifier,
+        user_id: Identifier,
+    ) -> anyhow::Result<()> {
+        let connection = self.get_connection().await?;
+        rooms_users::Entity::delete_by_id((room_id, user_id))
+            .exec(connection)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_room_users(
+        &self,
+        room_id: Identifier,
+    ) -> anyhow::Result<Vec<rooms_users::Model>> {
+        let connection = self.get_connection().await?;
+        let room_users = rooms_users::Entity::find()
+            .filter(rooms_users::Column::RoomId.eq(room_id))
+            .all(connection)
+            .await?;
+
+        Ok(room_users)
+    }
+
+    pub async fn get_members_by_room(
+        &self,
+        room_id: Identifier,
+    ) -> anyhow::Result<Vec<RoomMemberData>> {
+        let connection = self.get_connection().await?;
+        let query = r#"
+            SELECT u.id as id, u.username as username, ru.role as role
+            FROM users u
+            INNER JOIN rooms_users ru ON u.id = ru.user_id
+            WHERE ru.room_id = ?
+        "#;
+
+        let members: Vec<RoomMemberData> = RoomMemberData::find_by_statement(
+            Statement::from_sql_and_values(DbBackend::Sqlite, query, vec![room_id.into()]),
+        )
+        .all(connection)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Ids of users sharing at least one room with the user
+    pub async fn get_room_mate_ids(&self, user_id: Identifier) -> anyhow::Result<Vec<Identifier>> {
+        let connection = self.get_connection().await?;
+        let user_room_ids = Query::select()
+            .column(rooms_users::Column::RoomId)
+            .from(rooms_users::Entity)
+            .and_where(rooms_users::Column::UserId.eq(user_id))
+            .to_owned();
+
+        let room_mate_ids: Vec<Identifier> = rooms_users::Entity::find()
+            .select_only()
+            .column(rooms_users::Column::UserId)
+            .distinct()
+            .filter(rooms_users::Column::RoomId.in_subquery(user_room_ids))
+            .filter(rooms_users::Column::UserId.ne(user_id))
+            .into_tuple()
+            .all(connection)
+            .await?;
+
+        Ok(room_mate_ids)
+    }
+
     pub async fn get_users_by_room(
         &self,
         room_id: Identifier,
@@ -94,6 +359,31 @@ pub struct MessageRepository {
     pub lazy_connector: Arc<LazyConnector>,
 }
 
+/// Point from which room changes are replayed. When client knows its last message, newer
+/// messages are found by id, messages created within the same timestamp would be missed
+/// otherwise. Edits and deletes are always found by time.
+#[derive(Clone, Copy, Debug)]
+pub struct ChangesCursor {
+    pub since: NaiveDateTime,
+    pub last_message_id: Option<Identifier>,
+}
+
+impl ChangesCursor {
+    pub fn is_new(&self, message: &messages::Model) -> bool {
+        match self.last_message_id {
+            Some(last_message_id) => message.id > last_message_id,
+            None => message.created_at > self.since,
+        }
+    }
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct ReactionCountData {
+    pub message_id: Identifier,
+    pub emoji: String,
+    pub count: i64,
+}
+
 impl MessageRepository {
     pub async fn get_message_by_uuid(&self, uuid: Uuid) -> anyhow::Result<Option<messages::Model>> {
         let model = messages::Entity::find()
@@ -120,15 +410,436 @@ impl MessageRepository {
         Ok(messages)
     }
 
-    pub async fn mark_messages_read(&self, message_uuids: Vec<UuidIdentifier>) -> anyhow::Result<()> {
+    /// Messages of the room created, edited or deleted after the cursor, oldest first,
+    /// paged by the id of the last message of previous page
+    pub async fn get_messages_changed_since(
+        &self,
+        room_id: Identifier,
+        cursor: ChangesCursor,
+        after_id: Identifier,
+        limit: u64,
+    ) -> anyhow::Result<Vec<messages::Model>> {
+        let connection = self.get_connection().await?;
+        let is_new = match cursor.last_message_id {
+            Some(last_message_id) => messages::Column::Id.gt(last_message_id),
+            None => messages::Column::CreatedAt.gt(cursor.since),
+        };
+        let changed_since = Condition::any()
+            .add(is_new)
+            .add(messages::Column::EditedAt.gt(cursor.since))
+            .add(messages::Column::DeletedAt.gt(cursor.since));
+
+        let messages = messages::Entity::find()
+            .filter(messages::Column::RoomId.eq(room_id))
+            .filter(messages::Column::Id.gt(after_id))
+            .filter(changed_since)
+            .order_by_asc(messages::Column::Id)
+            .limit(limit)
+            .all(connection)
+            .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn get_messages_by_ids(
+        &self,
+        ids: Vec<Identifier>,
+    ) -> anyhow::Result<Vec<messages::Model>> {
+        let connection = self.get_connection().await?;
+        let messages = messages::Entity::find()
+            .filter(messages::Column::Id.is_in(ids))
+            .all(connection)
+            .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn mark_messages_read(
+        &self,
+        room_id: Identifier,
+        user_id: Identifier,
+        message_uuids: Vec<UuidIdentifier>,
+    ) -> anyhow::Result<Vec<UuidIdentifier>> {
+        let connection = self.get_connection().await?;
+
+        let room_user = rooms_users::Entity::find_by_id((room_id, user_id))
+            .one(connection)
+            .await?;
+
+        if room_user.is_none() {
+            return Ok(Vec::new());
+        }
+
+        // Only messages of this room written by someone else can be read by the user
+        let read_messages: Vec<(Identifier, UuidIdentifier)> = messages::Entity::find()
+            .select_only()
+            .column(messages::Column::Id)
+            .column(messages::Column::Uuid)
+            .filter(messages::Column::RoomId.eq(room_id))
+            .filter(messages::Column::UserId.ne(user_id))
+            .filter(messages::Column::Uuid.is_in(message_uuids))
+            .into_tuple()
+            .all(connection)
+            .await?;
+
+        let Some(last_read_message_id) = read_messages.iter().map(|(id, _)| *id).max() else {
+            return Ok(Vec::new());
+        };
+
+        // Cursor only moves forward, reading an older message again does not unread newer ones
+        rooms_users::Entity::update_many()
+            .col_expr(
+                rooms_users::Column::LastReadMessageId,
+                Expr::value(last_read_message_id),
+            )
+            .filter(rooms_users::Column::RoomId.eq(room_id))
+            .filter(rooms_users::Column::UserId.eq(user_id))
+            .filter(
+                rooms_users::Column::LastReadMessageId
+                    .is_null()
+                    .or(rooms_users::Column::LastReadMessageId.lt(last_read_message_id)),
+            )
+            .exec(connection)
+            .await?;
+
+        Ok(read_messages.into_iter().map(|(_, uuid)| uuid).collect())
+    }
+
+    pub async fn add_reaction(
+        &self,
+        message_id: Identifier,
+        user_id: Identifier,
+        emoji: String,
+    ) -> anyhow::Result<bool> {
         let connection = self.get_connection().await?;
+        let model = message_reactions::ActiveModel {
+            message_id: Set(message_id),
+            user_id: Set(user_id),
+            emoji: Set(emoji),
+            created_at: Set(Utc::now().naive_utc()),
+        };
+
+        let inserted_rows = message_reactions::Entity::insert(model)
+            .on_conflict(
+                OnConflict::columns([
+                    message_reactions::Column::MessageId,
+                    message_reactions::Column::UserId,
+                    message_reactions::Column::Emoji,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(connection)
+            .await?;
+
+        Ok(inserted_rows > 0)
+    }
 
-        messages::Entity::update_many()
-            .col_expr(messages::Column::Read, Expr::value(true))
-            .filter(Expr::col(messages::Column::Uuid).is_in(message_uuids))
+    pub async fn remove_reaction(
+        &self,
+        message_id: Identifier,
+        user_id: Identifier,
+        emoji: String,
+    ) -> anyhow::Result<bool> {
+        let connection = self.get_connection().await?;
+        let result = message_reactions::Entity::delete_by_id((message_id, user_id, emoji))
             .exec(connection)
             .await?;
 
-        Ok(())
+        Ok(result.rows_affected > 0)
+    }
+
+    pub async fn get_reaction_counts(
+        &self,
+        message_ids: Vec<Identifier>,
+    ) -> anyhow::Result<Vec<ReactionCountData>> {
+        let connection = self.get_connection().await?;
+        let reaction_counts = message_reactions::Entity::find()
+            .select_only()
+            .column(message_reactions::Column::MessageId)
+            .column(message_reactions::Column::Emoji)
+            .column_as(
+                Expr::col(message_reactions::Column::UserId).count(),
+                "count",
+            )
+            .filter(message_reactions::Column::MessageId.is_in(message_ids))
+            .group_by(message_reactions::Column::MessageId)
+            .group_by(message_reactions::Column::Emoji)
+            .order_by_asc(Expr::col(message_reactions::Column::CreatedAt).min())
+            .into_model::<ReactionCountData>()
+            .all(connection)
+            .await?;
+
+        Ok(reaction_counts)
+    }
+}
+
+#[derive(Clone)]
+pub struct SessionRepository {
+    pub lazy_connector: Arc<LazyConnector>,
+}
+
+impl SessionRepository {
+    pub async fn get_by_uuid(&self, uuid: Uuid) -> anyhow::Result<Option<sessions::Model>> {
+        let connection = self.get_connection().await?;
+        let session = sessions::Entity::find()
+            .filter(sessions::Column::Uuid.eq(uuid))
+            .one(connection)
+            .await?;
+
+        Ok(session)
+    }
+
+    pub async fn get_for_user(&self, user_id: Identifier) -> anyhow::Result<Vec<sessions::Model>> {
+        let connection = self.get_connection().await?;
+        let sessions = sessions::Entity::find()
+            .filter(sessions::Column::UserId.eq(user_id))
+            .order_by_desc(sessions::Column::LastRefreshedAt)
+            .all(connection)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    /// Access tokens stay valid only while their session exists and is not expired
+    pub async fn is_active(&self, uuid: Uuid) -> anyhow::Result<bool> {
+        let connection = self.get_connection().await?;
+        let count = sessions::Entity::find()
+            .filter(sessions::Column::Uuid.eq(uuid))
+            .filter(sessions::Column::ExpiresAt.gt(Utc::now().naive_utc()))
+            .count(connection)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Replaces refresh token hash only if the session was not rotated meanwhile, previous
+    /// hash is kept to detect reuse, returns whether the session was rotated
+    pub async fn rotate_refresh_token(
+        &self,
+        session: &sessions::Model,
+        refresh_token_hash: String,
+        refreshed_at: NaiveDateTime,
+        expires_at: NaiveDateTime,
+    ) -> anyhow::Result<bool> {
+        let connection = self.get_connection().await?;
+        let result = sessions::Entity::update_many()
+            .col_expr(
+                sessions::Column::RefreshTokenHash,
+                Expr::value(refresh_token_hash),
+            )
+            .col_expr(
+                sessions::Column::PreviousRefreshTokenHash,
+                Expr::value(session.refresh_token_hash.clone()),
+            )
+            .col_expr(sessions::Column::LastRefreshedAt, Expr::value(refreshed_at))
+            .col_expr(sessions::Column::ExpiresAt, Expr::value(expires_at))
+            .filter(sessions::Column::Id.eq(session.id))
+            .filter(sessions::Column::RefreshTokenHash.eq(session.refresh_token_hash.clone()))
+            .exec(connection)
+            .await?;
+
+        Ok(result.rows_affected == 1)
+    }
+
+    /// Expired sessions can't be refreshed and their access tokens are rejected anyway
+    pub async fn delete_expired(&self) -> anyhow::Result<u64> {
+        let connection = self.get_connection().await?;
+        let result = sessions::Entity::delete_many()
+            .filter(sessions::Column::ExpiresAt.lt(Utc::now().naive_utc()))
+            .exec(connection)
+            .await?;
+
+        Ok(result.rows_affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::{ActiveValue::Set, IntoActiveModel};
+
+    use crate::{db::RepositoryTrait, test_util};
+
+    use super::ChangesCursor;
+
+    #[tokio::test]
+    async fn member_marks_messages_of_room_read() {
+        let state = test_util::service_state().await;
+        let author = test_util::insert_user(&state, "author").await;
+        let reader = test_util::insert_user(&state, "reader").await;
+        let room = test_util::insert_group_room(&state, &[author.id, reader.id]).await;
+        let message = test_util::insert_message(&state, room.id, author.id).await;
+
+        let read_message_uuids = state
+            .message_repository
+            .mark_messages_read(room.id, reader.id, vec![message.uuid])
+            .await
+            .unwrap();
+
+        assert_eq!(read_message_uuids, vec![message.uuid]);
+        assert_eq!(
+            last_read_message_id(&state, room.id, reader.id).await,
+            Some(message.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn non_member_cannot_mark_messages_read() {
+        let state = test_util::service_state().await;
+        let author = test_util::insert_user(&state, "author").await;
+        let member = test_util::insert_user(&state, "member").await;
+        let outsider = test_util::insert_user(&state, "outsider").await;
+        let room = test_util::insert_group_room(&state, &[author.id, member.id]).await;
+        let message = test_util::insert_message(&state, room.id, author.id).await;
+
+        let read_message_uuids = state
+            .message_repository
+            .mark_messages_read(room.id, outsider.id, vec![message.uuid])
+            .await
+            .unwrap();
+
+        assert!(read_message_uuids.is_empty());
+        assert_eq!(last_read_message_id(&state, room.id, author.id).await, None);
+        assert_eq!(last_read_message_id(&state, room.id, member.id).await, None);
+        assert!(
+            state
+                .room_repository
+                .get_room_user(room.id, outsider.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn member_cannot_mark_messages_of_other_room_read() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let member = test_util::insert_user(&state, "member").await;
+        let other_member = test_util::insert_user(&state, "other member").await;
+        let room = test_util::insert_group_room(&state, &[user.id, member.id]).await;
+        let other_room = test_util::insert_group_room(&state, &[user.id, other_member.id]).await;
+        let other_room_message =
+            test_util::insert_message(&state, other_room.id, other_member.id).await;
+
+        // Neither a member of the other room nor a member of only this room can use its ids
+        for user_id in [user.id, member.id] {
+            let read_message_uuids = state
+                .message_repository
+                .mark_messages_read(room.id, user_id, vec![other_room_message.uuid])
+                .await
+                .unwrap();
+
+            assert!(read_message_uuids.is_empty());
+            assert_eq!(last_read_message_id(&state, room.id, user_id).await, None);
+        }
+
+        assert_eq!(
+            last_read_message_id(&state, other_room.id, user.id).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn changed_messages_are_paged_after_last_id() {
+        let state = test_util::service_state().await;
+        let author = test_util::insert_user(&state, "author").await;
+        let room = test_util::insert_group_room(&state, &[author.id]).await;
+        let cursor = ChangesCursor {
+            since: chrono::NaiveDateTime::MIN,
+            last_message_id: None,
+        };
+
+        let mut message_ids = Vec::new();
+        for _ in 0..3 {
+            let message = test_util::insert_message(&state, room.id, author.id).await;
+            message_ids.push(message.id);
+        }
+
+        let first_page = state
+            .message_repository
+            .get_messages_changed_since(room.id, cursor, 0, 2)
+            .await
+            .unwrap();
+        let second_page = state
+            .message_repository
+            .get_messages_changed_since(room.id, cursor, first_page[1].id, 2)
+            .await
+            .unwrap();
+
+        let paged_ids: Vec<i32> = first_page
+            .iter()
+            .chain(second_page.iter())
+            .map(|message| message.id)
+            .collect();
+
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(paged_ids, message_ids);
+    }
+
+    #[tokio::test]
+    async fn messages_created_with_last_message_timestamp_are_new() {
+        let state = test_util::service_state().await;
+        let author = test_util::insert_user(&state, "author").await;
+        let room = test_util::insert_group_room(&state, &[author.id]).await;
+        let last_message = test_util::insert_message(&state, room.id, author.id).await;
+        let same_time_message = test_util::insert_message(&state, room.id, author.id).await;
+
+        let mut same_time_model = same_time_message.clone().into_active_model();
+        same_time_model.created_at = Set(last_message.created_at);
+        state
+            .message_repository
+            .update(same_time_model)
+            .await
+            .unwrap();
+
+        let cursor = ChangesCursor {
+            since: last_message.created_at,
+            last_message_id: Some(last_message.id),
+        };
+
+        let messages = state
+            .message_repository
+            .get_messages_changed_since(room.id, cursor, 0, 10)
+            .await
+            .unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, same_time_message.id);
+        assert!(cursor.is_new(&messages[0]));
+    }
+
+    #[tokio::test]
+    async fn expired_session_is_inactive_and_deleted() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let hour = chrono::TimeDelta::hours(1);
+
+        let (active_session, _) = test_util::insert_session(&state, user.id, hour).await;
+        let (expired_session, _) = test_util::insert_session(&state, user.id, -hour).await;
+
+        let session_repository = &state.session_repository;
+        let is_active = session_repository.is_active(active_session.uuid).await;
+        let is_expired_active = session_repository.is_active(expired_session.uuid).await;
+
+        assert!(is_active.unwrap());
+        assert!(!is_expired_active.unwrap());
+        assert_eq!(session_repository.delete_expired().await.unwrap(), 1);
+
+        let sessions = session_repository.get_for_user(user.id).await.unwrap();
+        assert_eq!(sessions, vec![active_session]);
+    }
+
+    async fn last_read_message_id(
+        state: &crate::state::ServiceState,
+        room_id: i32,
+        user_id: i32,
+    ) -> Option<i32> {
+        state
+            .room_repository
+            .get_room_user(room_id, user_id)
+            .await
+            .unwrap()
+            .and_then(|room_user| room_user.last_read_message_id)
     }
 }
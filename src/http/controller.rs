@@ -1,36 +1,57 @@
 use axum::{
     Json,
     extract::{self, Query},
+    http::{HeaderName, header},
+    response::AppendHeaders,
 };
+use chrono::{TimeDelta, Utc};
 use nultr_shared_lib::{
     request::{
-        AuthenticatedUnexpectedErrorResponse, CreatePrivateRoomErrorResponse,
-        CreatePrivateRoomRequest, CreatePrivateRoomResponse, GetMessagesErrorResponse,
-        GetMessagesRequest, GetMessagesResponse, GetRoomsErrorResponse,
-        GetRoomsResponse, GetUsersErrorResponse, GetUsersResponse, LoginErrorResponse,
-        LoginRequest, LoginResponse, MessageResponse, RoomResponse, UnexpectedErrorResponse,
-        UserResponse,
+        AddRoomMemberErrorResponse, AddRoomMemberRequest, AddRoomMemberResponse,
+        AuthenticatedUnexpectedErrorResponse, CreateGroupRoomErrorResponse, CreateGroupRoomRequest,
+        CreateGroupRoomResponse, CreatePrivateRoomErrorResponse, CreatePrivateRoomRequest,
+        CreatePrivateRoomResponse, CreateWsTicketErrorResponse, CreateWsTicketResponse,
+        GetMessagesErrorResponse, GetMessagesRequest, GetMessagesResponse,
+        GetPresenceErrorResponse, GetPresenceRequest, GetPresenceResponse,
+        GetRoomMembersErrorResponse, GetRoomMembersRequest, GetRoomMembersResponse,
+        GetRoomsErrorResponse, GetRoomsResponse, GetSessionsErrorResponse, GetSessionsResponse,
+        GetUsersErrorResponse, GetUsersResponse, LeaveRoomErrorResponse, LeaveRoomRequest,
+        LeaveRoomResponse, LoginErrorResponse, LoginRequest, LoginResponse, LogoutErrorResponse,
+        LogoutResponse, MessageResponse, PresenceResponse, ReactionResponse,
+        RefreshTokenErrorResponse, RefreshTokenRequest, RefreshTokenResponse,
+        RemoveRoomMemberErrorResponse, RemoveRoomMemberRequest, RemoveRoomMemberResponse,
+        RenameRoomErrorResponse, RenameRoomRequest, RenameRoomResponse, RevokeSessionErrorResponse,
+        RevokeSessionRequest, RevokeSessionResponse, RoomMemberResponse, RoomResponse,
+        SessionResponse, SetRoomMemberRoleErrorResponse, SetRoomMemberRoleRequest,
+        SetRoomMemberRoleResponse, UnexpectedErrorResponse, UserResponse,
     },
     util::MonoResult,
 };
 use rust_api_kit::http::client::Response;
-use sea_orm::ActiveValue::Set;
+use sea_orm::{ActiveValue::Set, IntoActiveModel};
+use std::collections::HashMap;
+use uuid::Uuid;
 
 use crate::{
-    auth,
+    auth::{self, refresh_token::RefreshToken},
+    config,
     db::{
-        self, RepositoryTrait,
+        self, DbConnectionContainerTrait, RepositoryTrait,
         entity::{
             rooms::{self},
             rooms_users,
+            sea_orm_active_enums::RoomRole,
+            sessions,
         },
+        repository::RoomRepository,
     },
-    state,
+    state::{self, RoomMemberEvent, RoomMemberRoleChangedEvent, RoomRenamedEvent, ThreadEvent},
 };
 
 pub type AuthenticatedResponse<T, E> =
     MonoResult<Response<T, E, AuthenticatedUnexpectedErrorResponse>>;
 pub type UnauthenticatedResponse<T, E> = MonoResult<Response<T, E, UnexpectedErrorResponse>>;
+type TokenCookieHeader = AppendHeaders<Option<(HeaderName, String)>>;
 
 pub async fn get_users(
     extract::State(state): extract::State<state::ServiceState>,
@@ -60,10 +81,7 @@ pub async fn get_rooms(
         .get_for_user(claims.user_id)
         .await?
         .iter()
-        .map(|room| RoomResponse {
-            id: room.id,
-            name: room.name.clone(),
-        })
+        .map(RoomResponse::from)
         .collect();
 
     Ok(Response::Ok(GetRoomsResponse(rooms)))
@@ -74,8 +92,9 @@ pub async fn create_private_room(
     claims: auth::jwt::Claims,
     Json(input): Json<CreatePrivateRoomRequest>,
 ) -> AuthenticatedResponse<CreatePrivateRoomResponse, CreatePrivateRoomErrorResponse> {
-    //let txn = state.room_repository.begin_transaction().await?;
-    // TODO: validate, lock by current user
+    if input.receiver_user_id == claims.user_id {
+        return Err(CreatePrivateRoomErrorResponse::CannotCreateWithSelf.into());
+    }
 
     let (recipient_result, current_user_result) = tokio::join!(
         state.user_repository.get_by_id(input.receiver_user_id),
@@ -90,34 +109,79 @@ pub async fn create_private_room(
         CreatePrivateRoomErrorResponse::UserNotFound,
     ))?;
 
-    let room = state
+    let room_name_for_current_usr = recipient.username;
+
+    let existing_room = state
         .room_repository
-        .insert(rooms::ActiveModel {
-            name: Set(input.name),
-            ..Default::default()
-        })
+        .get_private_room(claims.user_id, recipient.id)
         .await?;
 
-    let room_name_for_current_usr = recipient.username;
+    if let Some(room) = existing_room {
+        return Ok(Response::Ok(CreatePrivateRoomResponse {
+            id: room.id,
+            name: room_name_for_current_usr,
+        }));
+    }
+
+    let txn = state.room_repository.begin_transaction().await?;
+
+    let insert_result = state
+        .room_repository
+        .insert_in(
+            &txn,
+            rooms::ActiveModel {
+                name: Set(input.name),
+                private_room_key: Set(Some(RoomRepository::private_room_key(
+                    claims.user_id,
+                    recipient.id,
+                ))),
+                ..Default::default()
+            },
+        )
+        .await;
+
+    let room = match insert_result {
+        Ok(room) => room,
+        // Concurrent request for the same pair won the race, reuse its room
+        Err(error) if db::is_unique_violation(&error) => {
+            txn.rollback().await?;
+
+            let room = state
+                .room_repository
+                .get_private_room(claims.user_id, recipient.id)
+                .await?
+                .ok_or(error)?;
+
+            return Ok(Response::Ok(CreatePrivateRoomResponse {
+                id: room.id,
+                name: room_name_for_current_usr,
+            }));
+        }
+        Err(error) => return Err(error.into()),
+    };
 
     let current_user_link = rooms_users::ActiveModel {
         room_id: Set(room.id),
         user_id: Set(claims.user_id),
         generated_room_name: Set(Some(room_name_for_current_usr.clone())),
+        role: Set(RoomRole::Owner),
+        ..Default::default()
     };
 
     let recipient_link = rooms_users::ActiveModel {
         room_id: Set(room.id),
         user_id: Set(recipient.id),
         generated_room_name: Set(Some(current_user.username)),
+        role: Set(RoomRole::Owner),
+        ..Default::default()
     };
 
     state
         .room_repository
-        .insert_rooms_users(vec![current_user_link, recipient_link])
+        .insert_rooms_users_in(&txn, vec![current_user_link, recipient_link])
         .await?;
 
-    //state.room_repository.end_transaction(txn).await?;
+    state.room_repository.end_transaction(txn).await?;
 
     Ok(Response::Ok(CreatePrivateRoomResponse {
         id: room.id,
@@ -125,6 +189,389 @@ pub async fn create_private_room(
     }))
 }
 
+pub async fn create_group_room(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<CreateGroupRoomRequest>,
+) -> AuthenticatedResponse<CreateGroupRoomResponse, CreateGroupRoomErrorResponse> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err(CreateGroupRoomErrorResponse::EmptyName.into());
+    }
+
+    let mut member_ids: Vec<_> = input
+        .member_ids
+        .into_iter()
+        .filter(|user_id| *user_id != claims.user_id)
+        .collect();
+    member_ids.sort_unstable();
+    member_ids.dedup();
+
+    let members = state.user_repository.get_by_ids(member_ids.clone()).await?;
+    if members.len() != member_ids.len() {
+        return Err(CreateGroupRoomErrorResponse::UserNotFound.into());
+    }
+
+    let txn = state.room_repository.begin_transaction().await?;
+
+    let room = state
+        .room_repository
+        .insert_in(
+            &txn,
+            rooms::ActiveModel {
+                name: Set(Some(name.clone())),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    let owner_link = rooms_users::ActiveModel {
+        room_id: Set(room.id),
+        user_id: Set(claims.user_id),
+        generated_room_name: Set(None),
+        role: Set(RoomRole::Owner),
+        ..Default::default()
+    };
+
+    let member_links = member_ids
+        .into_iter()
+        .map(|user_id| rooms_users::ActiveModel {
+            room_id: Set(room.id),
+            user_id: Set(user_id),
+            generated_room_name: Set(None),
+            role: Set(RoomRole::Member),
+            ..Default::default()
+        });
+
+    state
+        .room_repository
+        .insert_rooms_users_in(&txn, [owner_link].into_iter().chain(member_links).collect())
+        .await?;
+
+    state.room_repository.end_transaction(txn).await?;
+
+    Ok(Response::Ok(CreateGroupRoomResponse { id: room.id, name }))
+}
+
+pub async fn add_room_member(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<AddRoomMemberRequest>,
+) -> AuthenticatedResponse<AddRoomMemberResponse, AddRoomMemberErrorResponse> {
+    let room = state
+        .room_repository
+        .get_by_id(input.room_id)
+        .await?
+        .ok_or(Response::Error(AddRoomMemberErrorResponse::RoomNotFound))?;
+
+    if room.private_room_key.is_some() {
+        return Err(AddRoomMemberErrorResponse::PrivateRoom.into());
+    }
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(input.room_id)
+        .await?;
+
+    let current_member = room_members
+        .iter()
+        .find(|member| member.id == claims.user_id)
+        .ok_or(Response::Error(AddRoomMemberErrorResponse::NotMemberOfRoom))?;
+
+    if !current_member.role.can_manage_room() {
+        return Err(AddRoomMemberErrorResponse::PermissionDenied.into());
+    }
+
+    let is_already_member = room_members.iter().any(|member| member.id == input.user_id);
+    if is_already_member {
+        return Err(AddRoomMemberErrorResponse::AlreadyMember.into());
+    }
+
+    let user_exists = state.user_repository.exists_by_id(input.user_id).await?;
+    if !user_exists {
+        return Err(AddRoomMemberErrorResponse::UserNotFound.into());
+    }
+
+    state
+        .room_repository
+        .insert_rooms_users(vec![rooms_users::ActiveModel {
+            room_id: Set(input.room_id),
+            user_id: Set(input.user_id),
+            generated_room_name: Set(None),
+            role: Set(RoomRole::Member),
+            ..Default::default()
+        }])
+        .await?;
+
+    let thread_event = ThreadEvent::RoomMemberAdded(RoomMemberEvent {
+        room_id: input.room_id,
+        user_id: input.user_id,
+    });
+
+    let recipient_ids = room_members
+        .iter()
+        .map(|member| member.id)
+        .chain([input.user_id]);
+
+    send_thread_event_to_users(&state, recipient_ids, thread_event);
+
+    Ok(Response::Ok(AddRoomMemberResponse))
+}
+
+pub async fn remove_room_member(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<RemoveRoomMemberRequest>,
+) -> AuthenticatedResponse<RemoveRoomMemberResponse, RemoveRoomMemberErrorResponse> {
+    let room = state
+        .room_repository
+        .get_by_id(input.room_id)
+        .await?
+        .ok_or(Response::Error(RemoveRoomMemberErrorResponse::RoomNotFound))?;
+
+    if room.private_room_key.is_some() {
+        return Err(RemoveRoomMemberErrorResponse::PrivateRoom.into());
+    }
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(input.room_id)
+        .await?;
+
+    let current_member = room_members
+        .iter()
+        .find(|member| member.id == claims.user_id)
+        .ok_or(Response::Error(
+            RemoveRoomMemberErrorResponse::NotMemberOfRoom,
+        ))?;
+
+    let target_member = room_members
+        .iter()
+        .find(|member| member.id == input.user_id)
+        .ok_or(Response::Error(
+            RemoveRoomMemberErrorResponse::UserNotMemberOfRoom,
+        ))?;
+
+    let can_remove =
+        current_member.role.can_manage_room() && current_member.role.outranks(&target_member.role);
+    if !can_remove {
+        return Err(RemoveRoomMemberErrorResponse::PermissionDenied.into());
+    }
+
+    state
+        .room_repository
+        .delete_room_user(input.room_id, input.user_id)
+        .await?;
+
+    let thread_event = ThreadEvent::RoomMemberRemoved(RoomMemberEvent {
+        room_id: input.room_id,
+        user_id: input.user_id,
+    });
+
+    let recipient_ids = room_members.iter().map(|member| member.id);
+
+    send_thread_event_to_users(&state, recipient_ids, thread_event);
+
+    Ok(Response::Ok(RemoveRoomMemberResponse))
+}
+
+pub async fn leave_room(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<LeaveRoomRequest>,
+) -> AuthenticatedResponse<LeaveRoomResponse, LeaveRoomErrorResponse> {
+    let room = state
+        .room_repository
+        .get_by_id(input.room_id)
+        .await?
+        .ok_or(Response::Error(LeaveRoomErrorResponse::RoomNotFound))?;
+
+    if room.private_room_key.is_some() {
+        return Err(LeaveRoomErrorResponse::PrivateRoom.into());
+    }
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(input.room_id)
+        .await?;
+
+    let current_member = room_members
+        .iter()
+        .find(|member| member.id == claims.user_id)
+        .ok_or(Response::Error(LeaveRoomErrorResponse::NotMemberOfRoom))?;
+
+    let has_other_owner = room_members
+        .iter()
+        .any(|member| member.id != claims.user_id && member.role == RoomRole::Owner);
+    let is_last_owner = current_member.role == RoomRole::Owner && !has_other_owner;
+    if is_last_owner && room_members.len() > 1 {
+        return Err(LeaveRoomErrorResponse::LastOwner.into());
+    }
+
+    state
+        .room_repository
+        .delete_room_user(input.room_id, claims.user_id)
+        .await?;
+
+    let thread_event = ThreadEvent::RoomMemberRemoved(RoomMemberEvent {
+        room_id: input.room_id,
+        user_id: claims.user_id,
+    });
+
+    let recipient_ids = room_members.iter().map(|member| member.id);
+
+    send_thread_event_to_users(&state, recipient_ids, thread_event);
+
+    Ok(Response::Ok(LeaveRoomResponse))
+}
+
+pub async fn rename_room(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<RenameRoomRequest>,
+) -> AuthenticatedResponse<RenameRoomResponse, RenameRoomErrorResponse> {
+    let name = input.name.trim().to_string();
+    if name.is_empty() {
+        return Err(RenameRoomErrorResponse::EmptyName.into());
+    }
+
+    let room = state
+        .room_repository
+        .get_by_id(input.room_id)
+        .await?
+        .ok_or(Response::Error(RenameRoomErrorResponse::RoomNotFound))?;
+
+    // Members see each other's username as private room name, so rename would have no effect
+    if room.private_room_key.is_some() {
+        return Err(RenameRoomErrorResponse::PrivateRoom.into());
+    }
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(input.room_id)
+        .await?;
+
+    let current_member = room_members
+        .iter()
+        .find(|member| member.id == claims.user_id)
+        .ok_or(Response::Error(RenameRoomErrorResponse::NotMemberOfRoom))?;
+
+    if !current_member.role.can_manage_room() {
+        return Err(RenameRoomErrorResponse::PermissionDenied.into());
+    }
+
+    state
+        .room_repository
+        .update(rooms::ActiveModel {
+            id: Set(input.room_id),
+            name: Set(Some(name.clone())),
+            ..Default::default()
+        })
+        .await?;
+
+    let thread_event = ThreadEvent::RoomRenamed(RoomRenamedEvent {
+        room_id: input.room_id,
+        name,
+    });
+
+    let recipient_ids = room_members.iter().map(|member| member.id);
+
+    send_thread_event_to_users(&state, recipient_ids, thread_event);
+
+    Ok(Response::Ok(RenameRoomResponse))
+}
+
+pub async fn set_room_member_role(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<SetRoomMemberRoleRequest>,
+) -> AuthenticatedResponse<SetRoomMemberRoleResponse, SetRoomMemberRoleErrorResponse> {
+    let current_member = state
+        .room_repository
+        .get_room_user(input.room_id, claims.user_id)
+        .await?
+        .ok_or(Response::Error(
+            SetRoomMemberRoleErrorResponse::NotMemberOfRoom,
+        ))?;
+
+    if current_member.role != RoomRole::Owner || input.user_id == claims.user_id {
+        return Err(SetRoomMemberRoleErrorResponse::PermissionDenied.into());
+    }
+
+    let target_member = state
+        .room_repository
+        .get_room_user(input.room_id, input.user_id)
+        .await?
+        .ok_or(Response::Error(
+            SetRoomMemberRoleErrorResponse::UserNotMemberOfRoom,
+        ))?;
+
+    // Owners can't demote each other, so room never ends up without an owner
+    if target_member.role == RoomRole::Owner {
+        return Err(SetRoomMemberRoleErrorResponse::PermissionDenied.into());
+    }
+
+    let role: RoomRole = input.role.into();
+
+    state
+        .room_repository
+        .update_room_user_role(input.room_id, input.user_id, role)
+        .await?;
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(input.room_id)
+        .await?;
+
+    let thread_event = ThreadEvent::RoomMemberRoleChanged(RoomMemberRoleChangedEvent {
+        room_id: input.room_id,
+        user_id: input.user_id,
+        role: role.into(),
+    });
+
+    let recipient_ids = room_members.iter().map(|member| member.id);
+
+    send_thread_event_to_users(&state, recipient_ids, thread_event);
+
+    Ok(Response::Ok(SetRoomMemberRoleResponse))
+}
+
+pub async fn get_room_members(
+    Query(request): Query<GetRoomMembersRequest>,
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+) -> AuthenticatedResponse<GetRoomMembersResponse, GetRoomMembersErrorResponse> {
+    let room_exists = state.room_repository.exists_by_id(request.room_id).await?;
+    if !room_exists {
+        return Err(GetRoomMembersErrorResponse::RoomNotFound.into());
+    }
+
+    let room_members = state
+        .room_repository
+        .get_members_by_room(request.room_id)
+        .await?;
+
+    let is_member_of_room = room_members
+        .iter()
+        .any(|member| member.id == claims.user_id);
+    if !is_member_of_room {
+        return Err(GetRoomMembersErrorResponse::NotMemberOfRoom.into());
+    }
+
+    let members_response = GetRoomMembersResponse(
+        room_members
+            .iter()
+            .map(|member| RoomMemberResponse {
+                id: member.id,
+                username: member.username.clone(),
+                role: member.role.into(),
+            })
+            .collect(),
+    );
+
+    Ok(Response::Ok(members_response))
+}
+
 pub async fn get_messages(
     Query(request): Query<GetMessagesRequest>,
     extract::State(state): extract::State<state::ServiceState>,
@@ -137,13 +584,23 @@ pub async fn get_messages(
 
     let room_users = state
         .room_repository
-        .get_users_by_room(request.room_id)
+        .get_room_users(request.room_id)
         .await?;
 
-    let is_member_of_room = room_users.iter().any(|user| user.id == claims.user_id);
-    if !is_member_of_room {
-        return Err(GetMessagesErrorResponse::NotMemberOfRoom.into());
-    }
+    let current_room_user = room_users
+        .iter()
+        .find(|room_user| room_user.user_id == claims.user_id)
+        .ok_or(Response::Error(GetMessagesErrorResponse::NotMemberOfRoom))?;
+
+    let last_read_message_id = current_room_user.last_read_message_id.unwrap_or_default();
+
+    // Own message counts as read once every other member has read it
+    let last_read_by_others_message_id = room_users
+        .iter()
+        .filter(|room_user| room_user.user_id != claims.user_id)
+        .map(|room_user| room_user.last_read_message_id.unwrap_or_default())
+        .min()
+        .unwrap_or_default();
 
     let messages = state
         .message_repository
@@ -156,6 +613,22 @@ pub async fn get_messages(
         )
         .await?;
 
+    let reaction_counts = state
+        .message_repository
+        .get_reaction_counts(messages.iter().map(|message| message.id).collect())
+        .await?;
+
+    let mut reactions_by_message: HashMap<i32, Vec<ReactionResponse>> = HashMap::new();
+    for reaction_count in reaction_counts {
+        reactions_by_message
+            .entry(reaction_count.message_id)
+            .or_default()
+            .push(ReactionResponse {
+                emoji: reaction_count.emoji,
+                count: reaction_count.count as u64,
+            });
+    }
+
     let message_response = GetMessagesResponse(
         messages
             .iter()
@@ -164,7 +637,15 @@ pub async fn get_messages(
                 user_id: message.user_id,
                 content: message.content.clone(),
                 created_at: message.created_at,
-                read: message.read,
+                edited_at: message.edited_at,
+                deleted_at: message.deleted_at,
+                reply_to: message.reply_to,
+                reactions: reactions_by_message.remove(&message.id).unwrap_or_default(),
+                read: if message.user_id == claims.user_id {
+                    message.id <= last_read_by_others_message_id
+                } else {
+                    message.id <= last_read_message_id
+                },
             })
             .collect(),
     );
@@ -172,9 +653,58 @@ pub async fn get_messages(
     Ok(message_response.into())
 }
 
+pub async fn get_presence(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<GetPresenceRequest>,
+) -> AuthenticatedResponse<GetPresenceResponse, GetPresenceErrorResponse> {
+    // Presence is only visible to users sharing a room, same as live presence events
+    let room_mate_ids = state
+        .room_repository
+        .get_room_mate_ids(claims.user_id)
+        .await?;
+
+    let user_ids = input
+        .user_ids
+        .into_iter()
+        .filter(|user_id| *user_id == claims.user_id || room_mate_ids.contains(user_id))
+        .collect();
+
+    let users = state.user_repository.get_by_ids(user_ids).await?;
+
+    let presence_response = GetPresenceResponse(
+        users
+            .iter()
+            .map(|user| PresenceResponse {
+                user_id: user.id,
+                is_online: state.connection_registry.is_online(user.id),
+                last_seen_at: user.last_seen_at,
+            })
+            .collect(),
+    );
+
+    Ok(Response::Ok(presence_response))
+}
+
 pub async fn login(
     extract::State(state): extract::State<state::ServiceState>,
     Json(input): Json<LoginRequest>,
+) -> (
+    TokenCookieHeader,
+    UnauthenticatedResponse<LoginResponse, LoginErrorResponse>,
+) {
+    let response = create_session(&state, input).await;
+    let token = match &response {
+        Ok(Response::Ok(response)) => Some(response.token.as_str()),
+        _ => None,
+    };
+
+    (token_cookie_header(token), response)
+}
+
+async fn create_session(
+    state: &state::ServiceState,
+    input: LoginRequest,
 ) -> UnauthenticatedResponse<LoginResponse, LoginErrorResponse> {
     let user_result = state
         .user_repository
@@ -187,10 +717,29 @@ pub async fn login(
             .verify_password(input.password.as_str(), user.password_hash.as_str());
 
         if verified {
-            let token = state.jwt_encoder.encode(user.id)?;
+            let refresh_token = RefreshToken::generate(Uuid::new_v4());
+            let now = Utc::now().naive_utc();
+
+            let session = state
+                .session_repository
+                .insert(sessions::ActiveModel {
+                    uuid: Set(refresh_token.session_uuid),
+                    user_id: Set(user.id),
+                    refresh_token_hash: Set(state
+                        .password_hasher
+                        .hash_password(refresh_token.secret.as_str())),
+                    created_at: Set(now),
+                    last_refreshed_at: Set(now),
+                    expires_at: Set(now + refresh_token_ttl()),
+                    ..Default::default()
+                })
+                .await?;
+
+            let token = state.jwt_encoder.encode(user.id, session.uuid)?;
             let response = LoginResponse {
                 user_id: user.id,
                 token,
+                refresh_token: refresh_token.to_string(),
             };
 
             Ok(response.into())
@@ -201,3 +750,415 @@ pub async fn login(
         Err(LoginErrorResponse::AccessDenied.into())
     }
 }
+
+pub async fn refresh_token(
+    extract::State(state): extract::State<state::ServiceState>,
+    Json(input): Json<RefreshTokenRequest>,
+) -> (
+    TokenCookieHeader,
+    UnauthenticatedResponse<RefreshTokenResponse, RefreshTokenErrorResponse>,
+) {
+    let response = rotate_session(&state, input).await;
+    let token = match &response {
+        Ok(Response::Ok(response)) => Some(response.token.as_str()),
+        _ => None,
+    };
+
+    (token_cookie_header(token), response)
+}
+
+async fn rotate_session(
+    state: &state::ServiceState,
+    input: RefreshTokenRequest,
+) -> UnauthenticatedResponse<RefreshTokenResponse, RefreshTokenErrorResponse> {
+    let refresh_token = RefreshToken::parse(input.refresh_token.as_str())
+        .ok_or(Response::Error(RefreshTokenErrorResponse::InvalidToken))?;
+
+    let session = state
+        .session_repository
+        .get_by_uuid(refresh_token.session_uuid)
+        .await?
+        .ok_or(Response::Error(RefreshTokenErrorResponse::InvalidToken))?;
+
+    let now = Utc::now().naive_utc();
+
+    if session.expires_at < now {
+        terminate_session(state, session).await?;
+
+        return Err(RefreshTokenErrorResponse::InvalidToken.into());
+    }
+
+    let verified = state.password_hasher.verify_password(
+        refresh_token.secret.as_str(),
+        session.refresh_token_hash.as_str(),
+    );
+
+    if !verified {
+        // Secret of the previous rotation is presented only if token was copied, so it may be
+        // stolen, any other secret is just invalid and must not end the session
+        let is_reused = match session.previous_refresh_token_hash.as_deref() {
+            Some(previous_hash) => state
+                .password_hasher
+                .verify_password(refresh_token.secret.as_str(), previous_hash),
+            None => false,
+        };
+
+        if is_reused {
+            tracing::warn!("Refresh token reuse detected, session: {}", session.uuid);
+
+            terminate_session(state, session).await?;
+        }
+
+        return Err(RefreshTokenErrorResponse::InvalidToken.into());
+    }
+
+    let new_refresh_token = RefreshToken::generate(session.uuid);
+    let new_refresh_token_hash = state
+        .password_hasher
+        .hash_password(new_refresh_token.secret.as_str());
+
+    let is_rotated = state
+        .session_repository
+        .rotate_refresh_token(
+            &session,
+            new_refresh_token_hash,
+            now,
+            now + refresh_token_ttl(),
+        )
+        .await?;
+
+    // Concurrent refresh with the same token has already rotated the session
+    if !is_rotated {
+        return Err(RefreshTokenErrorResponse::InvalidToken.into());
+    }
+
+    let token = state
+        .jwt_encoder
+        .encode(session.user_id, new_refresh_token.session_uuid)?;
+
+    Ok(Response::Ok(RefreshTokenResponse {
+        token,
+        refresh_token: new_refresh_token.to_string(),
+    }))
+}
+
+pub async fn get_sessions(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+) -> AuthenticatedResponse<GetSessionsResponse, GetSessionsErrorResponse> {
+    let sessions = state
+        .session_repository
+        .get_for_user(claims.user_id)
+        .await?;
+
+    let sessions_response = GetSessionsResponse(
+        sessions
+            .iter()
+            .map(|session| SessionResponse {
+                uuid: session.uuid,
+                created_at: session.created_at,
+                last_refreshed_at: session.last_refreshed_at,
+                expires_at: session.expires_at,
+                is_current: session.uuid == claims.session_id,
+            })
+            .collect(),
+    );
+
+    Ok(Response::Ok(sessions_response))
+}
+
+pub async fn revoke_session(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+    Json(input): Json<RevokeSessionRequest>,
+) -> AuthenticatedResponse<RevokeSessionResponse, RevokeSessionErrorResponse> {
+    let session = state
+        .session_repository
+        .get_by_uuid(input.session_uuid)
+        .await?
+        .filter(|session| session.user_id == claims.user_id)
+        .ok_or(Response::Error(RevokeSessionErrorResponse::SessionNotFound))?;
+
+    terminate_session(&state, session).await?;
+
+    Ok(Response::Ok(RevokeSessionResponse))
+}
+
+pub async fn create_ws_ticket(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+) -> AuthenticatedResponse<CreateWsTicketResponse, CreateWsTicketErrorResponse> {
+    let ticket = state.ws_ticket_store.issue(claims);
+
+    Ok(Response::Ok(CreateWsTicketResponse { ticket }))
+}
+
+pub async fn logout(
+    extract::State(state): extract::State<state::ServiceState>,
+    claims: auth::jwt::Claims,
+) -> AuthenticatedResponse<LogoutResponse, LogoutErrorResponse> {
+    let session = state
+        .session_repository
+        .get_by_uuid(claims.session_id)
+        .await?;
+
+    if let Some(session) = session {
+        terminate_session(&state, session).await?;
+    }
+
+    Ok(Response::Ok(LogoutResponse))
+}
+
+/// Browser clients get access token as cookie too, so websocket handshake can use it
+fn token_cookie_header(token: Option<&str>) -> TokenCookieHeader {
+    AppendHeaders(token.map(|token| (header::SET_COOKIE, auth::http::ws_token_cookie(token))))
+}
+
+fn refresh_token_ttl() -> TimeDelta {
+    TimeDelta::days(*config::REFRESH_TOKEN_TTL_DAYS)
+}
+
+/// Deletes session, so its refresh token stops working, and closes its websocket connections
+async fn terminate_session(
+    state: &state::ServiceState,
+    session: sessions::Model,
+) -> anyhow::Result<()> {
+    let user_id = session.user_id;
+    let session_uuid = session.uuid;
+
+    state
+        .session_repository
+        .delete(session.into_active_model())
+        .await?;
+
+    send_thread_event_to_users(state, [user_id], ThreadEvent::SessionRevoked(session_uuid));
+
+    Ok(())
+}
+
+fn send_thread_event_to_users(
+    state: &state::ServiceState,
+    user_ids: impl IntoIterator<Item = i32>,
+    event: ThreadEvent,
+) {
+    for user_id in user_ids {
+        if let Err(error) = state
+            .connection_registry
+            .send_thread_event(user_id, event.clone())
+        {
+            tracing::error!("Thread event send error, user: {user_id}, error: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Json, extract,
+        extract::FromRequestParts,
+        http::{Request, header},
+    };
+    use chrono::Utc;
+    use nultr_shared_lib::request::{
+        CreateGroupRoomRequest, CreatePrivateRoomRequest, RefreshTokenRequest,
+    };
+    use sea_orm::ConnectionTrait;
+
+    use crate::{
+        auth::{self, refresh_token::RefreshToken},
+        db::{DbConnectionContainerTrait, RepositoryTrait},
+        state, test_util,
+    };
+
+    use super::{
+        create_group_room, create_private_room, logout, refresh_token_ttl, rotate_session,
+    };
+
+    async fn execute(state: &state::ServiceState, sql: &str) {
+        state
+            .room_repository
+            .get_connection()
+            .await
+            .unwrap()
+            .execute_unprepared(sql)
+            .await
+            .unwrap();
+    }
+
+    // Every membership insert fails after the room row is already written
+    async fn inject_rooms_users_insert_failure(state: &state::ServiceState) {
+        execute(
+            state,
+            "CREATE TRIGGER fail_rooms_users_insert BEFORE INSERT ON rooms_users
+            BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+        )
+        .await;
+    }
+
+    async fn remove_rooms_users_insert_failure(state: &state::ServiceState) {
+        execute(state, "DROP TRIGGER fail_rooms_users_insert").await;
+    }
+
+    #[tokio::test]
+    async fn create_group_room_leaves_no_room_when_member_insert_fails() {
+        let state = test_util::service_state().await;
+        let owner = test_util::insert_user(&state, "owner").await;
+        let member = test_util::insert_user(&state, "member").await;
+
+        inject_rooms_users_insert_failure(&state).await;
+
+        let result = create_group_room(
+            extract::State(state.clone()),
+            test_util::claims(owner.id),
+            Json(CreateGroupRoomRequest {
+                name: "group".to_string(),
+                member_ids: vec![member.id],
+            }),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(state.room_repository.get_all().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_private_room_leaves_no_room_when_member_insert_fails() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let recipient = test_util::insert_user(&state, "recipient").await;
+
+        inject_rooms_users_insert_failure(&state).await;
+
+        let result = create_private_room(
+            extract::State(state.clone()),
+            test_util::claims(user.id),
+            Json(CreatePrivateRoomRequest {
+                receiver_user_id: recipient.id,
+                name: None,
+            }),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert!(state.room_repository.get_all().await.unwrap().is_empty());
+
+        // Orphan room would hold the pair key and be returned without members
+        remove_rooms_users_insert_failure(&state).await;
+
+        let result = create_private_room(
+            extract::State(state.clone()),
+            test_util::claims(user.id),
+            Json(CreatePrivateRoomRequest {
+                receiver_user_id: recipient.id,
+                name: None,
+            }),
+        )
+        .await;
+
+        assert!(result.is_ok());
+
+        let room = state
+            .room_repository
+            .get_private_room(user.id, recipient.id)
+            .await
+            .unwrap()
+            .expect("Private room is not created");
+
+        let room_users = state.room_repository.get_room_users(room.id).await.unwrap();
+        assert_eq!(room_users.len(), 2);
+    }
+
+    async fn refresh(state: &state::ServiceState, token: &RefreshToken) -> bool {
+        let input = RefreshTokenRequest {
+            refresh_token: token.to_string(),
+        };
+
+        rotate_session(state, input).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn refresh_with_unknown_secret_keeps_session() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let (_, token) = test_util::insert_session(&state, user.id, refresh_token_ttl()).await;
+
+        let forged_token = RefreshToken {
+            session_uuid: token.session_uuid,
+            secret: "forged".to_string(),
+        };
+
+        assert!(!refresh(&state, &forged_token).await);
+        assert!(refresh(&state, &token).await);
+    }
+
+    #[tokio::test]
+    async fn refresh_with_rotated_secret_terminates_session() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let (_, token) = test_util::insert_session(&state, user.id, refresh_token_ttl()).await;
+
+        assert!(refresh(&state, &token).await);
+        assert!(!refresh(&state, &token).await);
+
+        let session = state
+            .session_repository
+            .get_by_uuid(token.session_uuid)
+            .await
+            .unwrap();
+
+        assert!(session.is_none());
+    }
+
+    #[tokio::test]
+    async fn rotation_of_already_rotated_session_is_rejected() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let (_, token) = test_util::insert_session(&state, user.id, refresh_token_ttl()).await;
+
+        // Both concurrent refreshes read the session before either of them rotated it
+        let session = state
+            .session_repository
+            .get_by_uuid(token.session_uuid)
+            .await
+            .unwrap()
+            .unwrap();
+        let now = Utc::now().naive_utc();
+
+        for (hash, is_rotated) in [("first", true), ("second", false)] {
+            let result = state
+                .session_repository
+                .rotate_refresh_token(&session, hash.to_string(), now, now)
+                .await
+                .unwrap();
+
+            assert_eq!(result, is_rotated);
+        }
+    }
+
+    async fn authenticate(state: &state::ServiceState, token: &str) -> Option<auth::jwt::Claims> {
+        let (mut parts, _) = Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        auth::jwt::Claims::from_request_parts(&mut parts, state)
+            .await
+            .ok()
+    }
+
+    #[tokio::test]
+    async fn token_of_logged_out_session_is_rejected() {
+        let state = test_util::service_state().await;
+        let user = test_util::insert_user(&state, "user").await;
+        let (session, _) = test_util::insert_session(&state, user.id, refresh_token_ttl()).await;
+        let token = state.jwt_encoder.encode(user.id, session.uuid).unwrap();
+
+        let claims = authenticate(&state, &token)
+            .await
+            .expect("Token of active session is rejected");
+
+        assert!(logout(extract::State(state.clone()), claims).await.is_ok());
+        assert!(authenticate(&state, &token).await.is_none());
+    }
+}
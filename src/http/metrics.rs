@@ -0,0 +1,29 @@
+use std::sync::atomic::Ordering;
+
+use axum::{extract::State, http::header};
+
+use crate::state;
+
+/// Websocket delivery metrics in Prometheus text format
+pub async fn get_metrics(
+    State(state): State<state::ServiceState>,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    let registry = &state.connection_registry;
+    let dropped_events = registry.metrics.dropped_events.load(Ordering::Relaxed);
+    let disconnected_slow_consumers = registry
+        .metrics
+        .disconnected_slow_consumers
+        .load(Ordering::Relaxed);
+
+    let body = format!(
+        "# TYPE nultr_ws_connections gauge\n\
+         nultr_ws_connections {}\n\
+         # TYPE nultr_ws_dropped_events_total counter\n\
+         nultr_ws_dropped_events_total {dropped_events}\n\
+         # TYPE nultr_ws_disconnected_slow_consumers_total counter\n\
+         nultr_ws_disconnected_slow_consumers_total {disconnected_slow_consumers}\n",
+        registry.connection_count()
+    );
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -5,6 +5,8 @@ mod db;
 mod http;
 mod server;
 mod state;
+#[cfg(test)]
+mod test_util;
 mod ws;
 
 use clap::Parser;
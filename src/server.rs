@@ -1,13 +1,19 @@
 use axum::{
     Router,
     extract::{self},
-    routing::any,
+    routing::{any, get},
+};
+use nultr_shared_lib::request::{
+    AddRoomMemberRequest, CreateGroupRoomRequest, CreatePrivateRoomRequest, CreateWsTicketRequest,
+    GetMessagesRequest, GetPresenceRequest, GetRoomMembersRequest, GetRoomsRequest,
+    GetSessionsRequest, GetUsersRequest, LeaveRoomRequest, LoginRequest, LogoutRequest,
+    RefreshTokenRequest, RemoveRoomMemberRequest, RenameRoomRequest, RevokeSessionRequest,
+    SetRoomMemberRoleRequest,
 };
-use nultr_shared_lib::request::{CreatePrivateRoomRequest, GetMessagesRequest, GetRoomsRequest, GetUsersRequest, LoginRequest};
 use rust_api_kit::generate_routes;
-use tokio::sync::Mutex;
 
-use std::{collections::HashMap, net::SocketAddr, path::PathBuf, sync::Arc};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use tokio::time;
 use tower_http::services::ServeDir;
 
 use axum::extract::connect_info::ConnectInfo;
@@ -16,23 +22,36 @@ use crate::{auth, config, http, state, ws};
 
 pub async fn serve() {
     let assets_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+    let state = state::ServiceState::default();
+
+    tokio::spawn(delete_expired_sessions(state.clone()));
 
     let http_api_routes = generate_routes! {
         LoginRequest => http::controller::login,
         GetUsersRequest => http::controller::get_users,
         GetMessagesRequest => http::controller::get_messages,
         CreatePrivateRoomRequest => http::controller::create_private_room,
+        CreateGroupRoomRequest => http::controller::create_group_room,
+        AddRoomMemberRequest => http::controller::add_room_member,
+        RemoveRoomMemberRequest => http::controller::remove_room_member,
+        LeaveRoomRequest => http::controller::leave_room,
+        RenameRoomRequest => http::controller::rename_room,
+        SetRoomMemberRoleRequest => http::controller::set_room_member_role,
+        GetRoomMembersRequest => http::controller::get_room_members,
+        GetPresenceRequest => http::controller::get_presence,
+        RefreshTokenRequest => http::controller::refresh_token,
+        GetSessionsRequest => http::controller::get_sessions,
+        RevokeSessionRequest => http::controller::revoke_session,
+        LogoutRequest => http::controller::logout,
+        CreateWsTicketRequest => http::controller::create_ws_ticket,
         GetRoomsRequest => http::controller::get_rooms
     };
 
-    let ws_state = Arc::new(Mutex::new(state::MutexState {
-        user_message_sender_map: HashMap::new(),
-    }));
-
     // build our application with some routes
     let app = Router::new()
         .fallback_service(ServeDir::new(assets_dir).append_index_html_on_directories(true))
         .merge(http_api_routes)
+        .route("/metrics", get(http::metrics::get_metrics))
         .route(
             "/ws",
             any({
@@ -40,11 +59,11 @@ pub async fn serve() {
                 ConnectInfo(addr): ConnectInfo<SocketAddr>,
                 extract::State(state): extract::State<state::ServiceState>,
                 claims: auth::jwt::Claims| {
-                    ws::connector::handle(ws, addr, state, claims, ws_state.clone())
+                    ws::connector::handle(ws, addr, state, claims)
                 }
             }),
         )
-        .with_state(state::ServiceState::default());
+        .with_state(state);
 //        .layer(
 //            TraceLayer::new_for_http()
 //            .make_span_with(DefaultMakeSpan::default().include_headers(true)),
@@ -64,3 +83,17 @@ pub async fn serve() {
         .unwrap();
 
 }
+
+/// Expired sessions are rejected anyway, they are deleted only to keep the table small
+async fn delete_expired_sessions(state: state::ServiceState) {
+    let mut interval = time::interval(Duration::from_secs(*config::SESSION_CLEANUP_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        match state.session_repository.delete_expired().await {
+            Ok(deleted_count) => tracing::debug!("Expired sessions deleted: {deleted_count}"),
+            Err(error) => tracing::error!("Expired sessions cleanup error: {error}"),
+        }
+    }
+}
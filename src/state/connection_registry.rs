@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        RwLock, RwLockReadGuard, RwLockWriteGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use anyhow::anyhow;
+use tokio::sync::mpsc::{Sender, error::TrySendError};
+use uuid::Uuid;
+
+use super::ThreadEvent;
+
+// Users are spread over shards, so fan-out to different users does not contend on one lock
+const SHARD_COUNT: usize = 16;
+
+pub type ConnectionId = Uuid;
+
+type UserSenders = HashMap<ConnectionId, Sender<ThreadEvent>>;
+
+#[derive(Default)]
+pub struct ConnectionMetrics {
+    /// Events which could not be queued, counted once per connection that missed them
+    pub dropped_events: AtomicU64,
+    pub disconnected_slow_consumers: AtomicU64,
+}
+
+pub struct ConnectionRegistry {
+    shards: Vec<RwLock<HashMap<i32, UserSenders>>>,
+    pub metrics: ConnectionMetrics,
+}
+
+impl Default for ConnectionRegistry {
+    fn default() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT).map(|_| RwLock::default()).collect(),
+            metrics: ConnectionMetrics::default(),
+        }
+    }
+}
+
+impl ConnectionRegistry {
+    /// Returns true if it is the first connection of the user
+    pub fn register_connection(
+        &self,
+        user_id: i32,
+        connection_id: ConnectionId,
+        sender: Sender<ThreadEvent>,
+    ) -> bool {
+        let mut shard = self.write_shard(user_id);
+        let user_senders = shard.entry(user_id).or_default();
+        user_senders.insert(connection_id, sender);
+
+        user_senders.len() == 1
+    }
+
+    /// Returns true if user has no connections left, only for the call which removes the user
+    pub fn unregister_connection(&self, user_id: i32, connection_id: ConnectionId) -> bool {
+        let mut shard = self.write_shard(user_id);
+
+        let Some(user_senders) = shard.get_mut(&user_id) else {
+            return false;
+        };
+
+        // Connection could be already dropped as slow consumer, user entry is kept until every
+        // connection is unregistered
+        user_senders.remove(&connection_id);
+
+        if user_senders.is_empty() {
+            shard.remove(&user_id);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn send_thread_event(&self, user_id: i32, event: ThreadEvent) -> anyhow::Result<()> {
+        self.send_thread_event_except(user_id, None, event)
+    }
+
+    /// Sends event to every connection of the user except the given one
+    pub fn send_thread_event_to_other_connections(
+        &self,
+        user_id: i32,
+        connection_id: ConnectionId,
+        event: ThreadEvent,
+    ) -> anyhow::Result<()> {
+        self.send_thread_event_except(user_id, Some(connection_id), event)
+    }
+
+    pub fn is_online(&self, user_id: i32) -> bool {
+        self.read_shard(user_id)
+            .get(&user_id)
+            .is_some_and(|user_senders| !user_senders.is_empty())
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| {
+                shard
+                    .read()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .values()
+                    .map(|user_senders| user_senders.len())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Connection which queue is full is dropped from registry, so its receiver is closed
+    /// after queued events are delivered and client has to reconnect and resume
+    fn send_thread_event_except(
+        &self,
+        user_id: i32,
+        excluded_connection_id: Option<ConnectionId>,
+        event: ThreadEvent,
+    ) -> anyhow::Result<()> {
+        let mut result = Ok(());
+        let mut slow_connection_ids = Vec::new();
+
+        // Sending only needs read access, write lock is taken just to drop slow consumers
+        if let Some(user_senders) = self.read_shard(user_id).get(&user_id) {
+            for (connection_id, user_sender) in user_senders {
+                if Some(*connection_id) == excluded_connection_id {
+                    continue;
+                }
+
+                let Err(error) = user_sender.try_send(event.clone()) else {
+                    continue;
+                };
+
+                self.metrics.dropped_events.fetch_add(1, Ordering::Relaxed);
+
+                match error {
+                    TrySendError::Full(_) => slow_connection_ids.push(*connection_id),
+                    TrySendError::Closed(_) => result = Err(anyhow!(error.to_string())),
+                }
+            }
+        }
+
+        if !slow_connection_ids.is_empty() {
+            self.drop_slow_consumers(user_id, slow_connection_ids);
+        }
+
+        result
+    }
+
+    fn drop_slow_consumers(&self, user_id: i32, connection_ids: Vec<ConnectionId>) {
+        let mut shard = self.write_shard(user_id);
+
+        let Some(user_senders) = shard.get_mut(&user_id) else {
+            return;
+        };
+
+        for connection_id in connection_ids {
+            user_senders.remove(&connection_id);
+
+            let disconnected_count = self
+                .metrics
+                .disconnected_slow_consumers
+                .fetch_add(1, Ordering::Relaxed)
+                + 1;
+
+            tracing::warn!(
+                "Slow consumer disconnected, user: {user_id}, connection: {connection_id}, total: {disconnected_count}"
+            );
+        }
+    }
+
+    fn read_shard(&self, user_id: i32) -> RwLockReadGuard<'_, HashMap<i32, UserSenders>> {
+        self.shards[Self::shard_index(user_id)]
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_shard(&self, user_id: i32) -> RwLockWriteGuard<'_, HashMap<i32, UserSenders>> {
+        self.shards[Self::shard_index(user_id)]
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn shard_index(user_id: i32) -> usize {
+        user_id.unsigned_abs() as usize % SHARD_COUNT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn stalled_receiver_is_dropped_when_queue_is_full() {
+        let registry = ConnectionRegistry::default();
+        let (sender, mut receiver) = mpsc::channel(2);
+        let (other_sender, mut other_receiver) = mpsc::channel(8);
+        let user_id = 1;
+
+        registry.register_connection(user_id, Uuid::new_v4(), sender);
+        registry.register_connection(user_id + 1, Uuid::new_v4(), other_sender);
+
+        // Receiver is never polled, so the third event does not fit
+        for _ in 0..3 {
+            registry
+                .send_thread_event(user_id, ThreadEvent::SessionRevoked(Uuid::new_v4()))
+                .unwrap();
+        }
+
+        assert!(!registry.is_online(user_id));
+        assert_eq!(registry.connection_count(), 1);
+        assert_eq!(registry.metrics.dropped_events.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            registry
+                .metrics
+                .disconnected_slow_consumers
+                .load(Ordering::Relaxed),
+            1
+        );
+
+        // Queued events are still delivered, then receiver is closed
+        assert!(receiver.try_recv().is_ok());
+        assert!(receiver.try_recv().is_ok());
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+
+        // Other users are not affected
+        registry
+            .send_thread_event(user_id + 1, ThreadEvent::SessionRevoked(Uuid::new_v4()))
+            .unwrap();
+
+        assert!(registry.is_online(user_id + 1));
+        assert!(other_receiver.try_recv().is_ok());
+    }
+
+    #[test]
+    fn user_goes_offline_once_after_slow_connections_are_unregistered() {
+        let registry = ConnectionRegistry::default();
+        let (sender, _receiver) = mpsc::channel(1);
+        let (other_sender, _other_receiver) = mpsc::channel(1);
+        let connection_id = Uuid::new_v4();
+        let other_connection_id = Uuid::new_v4();
+        let user_id = 1;
+
+        registry.register_connection(user_id, connection_id, sender);
+        registry.register_connection(user_id, other_connection_id, other_sender);
+
+        for _ in 0..2 {
+            registry
+                .send_thread_event(user_id, ThreadEvent::SessionRevoked(Uuid::new_v4()))
+                .unwrap();
+        }
+
+        assert!(!registry.is_online(user_id));
+        assert!(registry.unregister_connection(user_id, connection_id));
+        assert!(!registry.unregister_connection(user_id, other_connection_id));
+    }
+
+    #[test]
+    fn every_event_sent_to_closed_receiver_is_counted() {
+        let registry = ConnectionRegistry::default();
+        let (sender, receiver) = mpsc::channel(8);
+        let user_id = 1;
+
+        registry.register_connection(user_id, Uuid::new_v4(), sender);
+        drop(receiver);
+
+        for _ in 0..3 {
+            let result =
+                registry.send_thread_event(user_id, ThreadEvent::SessionRevoked(Uuid::new_v4()));
+
+            assert!(result.is_err());
+        }
+
+        assert_eq!(registry.metrics.dropped_events.load(Ordering::Relaxed), 3);
+    }
+}
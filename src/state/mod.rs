@@ -1,28 +1,60 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
-use nultr_shared_lib::request::WsMarkMessagesReadRequest;
-use tokio::sync::mpsc;
+use nultr_shared_lib::request::{
+    RoomResponse, WsMessageDeletedResponse, WsMessageEditedResponse, WsMessagesReadResponse,
+    WsPresenceEvent, WsReactionResponse, WsRoomMemberEvent, WsRoomMemberRoleChangedEvent,
+    WsRoomRenamedEvent, WsTypingEvent,
+};
 use uuid::Uuid;
 
-use crate::{auth, db::{self, repository::{MessageRepository, RoomRepository, UserRepository}}};
-
-pub type MessagesReadEvent = WsMarkMessagesReadRequest;
+use crate::{
+    auth,
+    db::{
+        self,
+        repository::{MessageRepository, RoomRepository, SessionRepository, UserRepository},
+    },
+};
+
+mod connection_registry;
+
+pub use connection_registry::{ConnectionId, ConnectionRegistry};
+
+pub type MessagesReadEvent = WsMessagesReadResponse;
+pub type MessageEditedEvent = WsMessageEditedResponse;
+pub type MessageDeletedEvent = WsMessageDeletedResponse;
+pub type ReactionEvent = WsReactionResponse;
+pub type RoomMemberEvent = WsRoomMemberEvent;
+pub type RoomMemberRoleChangedEvent = WsRoomMemberRoleChangedEvent;
+pub type RoomRenamedEvent = WsRoomRenamedEvent;
+pub type RoomSummaryEvent = RoomResponse;
+pub type TypingEvent = WsTypingEvent;
+pub type PresenceEvent = WsPresenceEvent;
 
 #[derive(Clone)]
 pub enum ThreadEvent {
     UserMessage(UserMessage),
     MessagesRead(MessagesReadEvent),
+    MessageEdited(MessageEditedEvent),
+    MessageDeleted(MessageDeletedEvent),
+    ReactionAdded(ReactionEvent),
+    ReactionRemoved(ReactionEvent),
+    RoomMemberAdded(RoomMemberEvent),
+    RoomMemberRemoved(RoomMemberEvent),
+    RoomMemberRoleChanged(RoomMemberRoleChangedEvent),
+    RoomRenamed(RoomRenamedEvent),
+    RoomSummary(RoomSummaryEvent),
+    Typing(TypingEvent),
+    Presence(PresenceEvent),
+    SessionRevoked(Uuid),
 }
 
 #[derive(Clone)]
 pub struct UserMessage {
     pub uuid: Uuid,
+    pub room_id: i32,
     pub from_user_id: i32,
     pub content: String,
-}
-
-pub struct MutexState {
-    pub user_message_sender_map: HashMap<i32, mpsc::UnboundedSender<ThreadEvent>>,
+    pub reply_to: Option<Uuid>,
 }
 
 #[derive(Clone)]
@@ -30,13 +62,24 @@ pub struct ServiceState {
     pub user_repository: UserRepository,
     pub room_repository: RoomRepository,
     pub message_repository: MessageRepository,
+    pub session_repository: SessionRepository,
     pub password_hasher: auth::PasswordHasher,
     pub jwt_encoder: auth::jwt::Encoder,
+    pub ws_ticket_store: Arc<auth::ws_ticket::TicketStore>,
+    pub connection_registry: Arc<ConnectionRegistry>,
 }
 
 impl Default for ServiceState {
     fn default() -> Self {
-        let lazy_connector = Arc::new(db::LazyConnector::default());
+        Self::new(
+            Arc::new(db::LazyConnector::default()),
+            auth::jwt::Encoder::default(),
+        )
+    }
+}
+
+impl ServiceState {
+    pub fn new(lazy_connector: Arc<db::LazyConnector>, jwt_encoder: auth::jwt::Encoder) -> Self {
         let room_repository = RoomRepository {
             lazy_connector: lazy_connector.clone(),
         };
@@ -45,18 +88,27 @@ impl Default for ServiceState {
             lazy_connector: lazy_connector.clone(),
         };
 
-        let message_repository = MessageRepository { lazy_connector };
+        let message_repository = MessageRepository {
+            lazy_connector: lazy_connector.clone(),
+        };
+
+        let session_repository = SessionRepository { lazy_connector };
 
         let password_hasher = auth::PasswordHasher::default();
 
-        let jwt_encoder = auth::jwt::Encoder::default();
+        let ws_ticket_store = Arc::new(auth::ws_ticket::TicketStore::default());
+
+        let connection_registry = Arc::new(ConnectionRegistry::default());
 
         Self {
             user_repository,
             room_repository,
             message_repository,
+            session_repository,
             password_hasher,
             jwt_encoder,
+            ws_ticket_store,
+            connection_registry,
         }
     }
 }
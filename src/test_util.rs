@@ -0,0 +1,127 @@
+use std::sync::Arc;
+
+use chrono::{TimeDelta, Utc};
+use sea_orm::ActiveValue::Set;
+use uuid::Uuid;
+
+use crate::{
+    auth::{self, refresh_token::RefreshToken},
+    db::{
+        self, RepositoryTrait,
+        entity::{messages, rooms, rooms_users, sea_orm_active_enums::RoomRole, sessions, users},
+    },
+    state,
+};
+
+pub async fn service_state() -> state::ServiceState {
+    let lazy_connector = Arc::new(db::LazyConnector::in_memory().await);
+
+    state::ServiceState::new(
+        lazy_connector,
+        auth::jwt::Encoder::new("test-secret".to_string()),
+    )
+}
+
+pub fn claims(user_id: i32) -> auth::jwt::Claims {
+    auth::jwt::Claims {
+        user_id,
+        session_id: Uuid::new_v4(),
+        jti: Uuid::new_v4(),
+        exp: usize::MAX,
+    }
+}
+
+pub async fn insert_user(state: &state::ServiceState, username: &str) -> users::Model {
+    state
+        .user_repository
+        .insert(users::ActiveModel {
+            username: Set(username.to_string()),
+            password_hash: Set(String::new()),
+            ..Default::default()
+        })
+        .await
+        .expect("User insert failed")
+}
+
+/// Group room, the first user is owner, the rest are members
+pub async fn insert_group_room(state: &state::ServiceState, user_ids: &[i32]) -> rooms::Model {
+    let room = state
+        .room_repository
+        .insert(rooms::ActiveModel {
+            name: Set(Some("group".to_string())),
+            ..Default::default()
+        })
+        .await
+        .expect("Room insert failed");
+
+    let room_users = user_ids
+        .iter()
+        .enumerate()
+        .map(|(index, user_id)| rooms_users::ActiveModel {
+            room_id: Set(room.id),
+            user_id: Set(*user_id),
+            generated_room_name: Set(None),
+            role: Set(if index == 0 {
+                RoomRole::Owner
+            } else {
+                RoomRole::Member
+            }),
+            ..Default::default()
+        })
+        .collect();
+
+    state
+        .room_repository
+        .insert_rooms_users(room_users)
+        .await
+        .expect("Room users insert failed");
+
+    room
+}
+
+pub async fn insert_message(
+    state: &state::ServiceState,
+    room_id: i32,
+    user_id: i32,
+) -> messages::Model {
+    state
+        .message_repository
+        .insert(messages::ActiveModel {
+            uuid: Set(Uuid::new_v4()),
+            user_id: Set(user_id),
+            room_id: Set(room_id),
+            content: Set("message".to_string()),
+            created_at: Set(Utc::now().naive_utc()),
+            ..Default::default()
+        })
+        .await
+        .expect("Message insert failed")
+}
+
+/// Session stores refresh token hash like login does, negative lifetime gives expired session
+pub async fn insert_session(
+    state: &state::ServiceState,
+    user_id: i32,
+    expires_in: TimeDelta,
+) -> (sessions::Model, RefreshToken) {
+    let refresh_token = RefreshToken::generate(Uuid::new_v4());
+    let now = Utc::now().naive_utc();
+
+    let session = state
+        .session_repository
+        .insert(sessions::ActiveModel {
+            uuid: Set(refresh_token.session_uuid),
+            user_id: Set(user_id),
+            refresh_token_hash: Set(state
+                .password_hasher
+                .hash_password(refresh_token.secret.as_str())),
+            created_at: Set(now),
+            last_refreshed_at: Set(now),
+            expires_at: Set(now + expires_in),
+            ..Default::default()
+        })
+        .await
+        .expect("Session insert failed");
+
+    (session, refresh_token)
+}
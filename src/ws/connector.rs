@@ -2,61 +2,63 @@ use axum::{
     extract::ws::{WebSocket, WebSocketUpgrade},
     response::IntoResponse,
 };
-use tokio::sync::{
-    Mutex,
-    mpsc::{self, UnboundedReceiver},
-};
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+use uuid::Uuid;
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
 
 use futures::stream::StreamExt;
 
 use crate::{
-    auth,
+    auth, config,
     state::{self, ThreadEvent},
 };
 
-use super::controller;
+use super::{controller, presence};
+
+const WS_PROTOCOL: &str = "nultr";
 
 pub async fn handle(
     ws: WebSocketUpgrade,
     addr: SocketAddr,
     service_state: state::ServiceState,
     claims: auth::jwt::Claims,
-    mutex_state: Arc<Mutex<state::MutexState>>,
 ) -> impl IntoResponse {
     tracing::debug!("{addr} connected.");
 
-    let (tx, rx) = mpsc::unbounded_channel::<ThreadEvent>();
-
-    mutex_state
-        .lock()
-        .await
-        .user_message_sender_map
-        .insert(claims.user_id, tx);
-
-    ws.on_upgrade(move |socket| handle_socket(socket, addr, service_state, mutex_state, claims, rx))
+    // Browser drops connection if none of requested subprotocols is selected, so client
+    // passing token as subprotocol has to request this one too
+    ws.protocols([WS_PROTOCOL])
+        .on_upgrade(move |socket| handle_socket(socket, addr, service_state, claims))
 }
 
 async fn handle_socket(
     socket: WebSocket,
     addr: SocketAddr,
     service_state: state::ServiceState,
-    mutex_state: Arc<Mutex<state::MutexState>>,
     claims: auth::jwt::Claims,
-    user_message_receiver: UnboundedReceiver<ThreadEvent>,
 ) {
     let user_id = claims.user_id;
+    let connection_id = Uuid::new_v4();
+
+    let (tx, user_message_receiver) = mpsc::channel::<ThreadEvent>(*config::WS_EVENT_QUEUE_SIZE);
+
+    presence::connect(&service_state, user_id, connection_id, tx).await;
 
     let (ws_sender, ws_receiver) = socket.split();
 
     let mut handler = controller::Controller {
-        mutex_state: mutex_state.clone(),
-        service_state,
+        service_state: service_state.clone(),
         claims,
+        connection_id,
         user_message_receiver,
         ws_sender,
         ws_receiver,
+        typing_deadlines: HashMap::new(),
+        ping_interval: ping_interval(),
+        last_activity_at: Instant::now(),
+        is_closed: false,
     };
 
     tracing::debug!("Websocket handler started {addr}");
@@ -68,11 +70,19 @@ async fn handle_socket(
         }
     }
 
+    if let Err(error) = handler.stop_typing_in_all_rooms().await {
+        tracing::error!("Websocket typing cleanup error: {error}");
+    }
+
     tracing::debug!("Websocket context {addr} destroyed");
 
-    mutex_state
-        .lock()
-        .await
-        .user_message_sender_map
-        .remove(&user_id);
+    presence::disconnect(&service_state, user_id, connection_id).await;
+}
+
+fn ping_interval() -> Interval {
+    let period = Duration::from_secs(*config::WS_PING_INTERVAL_SECS);
+    let mut ping_interval = time::interval_at(Instant::now() + period, period);
+    ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    ping_interval
 }
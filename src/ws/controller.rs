@@ -1,41 +1,82 @@
 use anyhow::anyhow;
 use nultr_shared_lib::request::{
-    WsErrorResponse, WsMarkMessagesReadRequest, WsMessageRequest,
-    WsMessageResponse, WsOkResponse, WsRequest, WsResponse,
+    WsDeleteMessageRequest, WsEditMessageRequest, WsErrorResponse, WsMarkMessagesReadRequest,
+    WsMessageRequest, WsMessageResponse, WsOkResponse, WsReactionRequest, WsReadCursorResponse,
+    WsRefreshTokenRequest, WsRequest, WsResponse, WsResumeCompletedResponse, WsResumeRequest,
+    WsTypingRequest,
 };
-use std::sync::Arc;
-
-use axum::extract::ws;
+use axum::{body::Bytes, extract::ws};
 
 use chrono::Utc;
 use futures::stream::StreamExt;
 
 use futures::SinkExt;
 use futures::stream::{SplitSink, SplitStream};
-use sea_orm::ActiveValue::Set;
-use tokio::sync::Mutex;
-use tokio::sync::mpsc::UnboundedReceiver;
+use sea_orm::{ActiveValue::Set, IntoActiveModel};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{self, Instant, Interval};
+
+use crate::db::{
+    RepositoryTrait,
+    entity::{messages, rooms_users, users},
+    repository::ChangesCursor,
+};
+use crate::state::{
+    ConnectionId, MessageDeletedEvent, MessageEditedEvent, MessagesReadEvent, ReactionEvent,
+    RoomSummaryEvent, ThreadEvent, TypingEvent, UserMessage,
+};
+use crate::{auth, config, state};
 
-use crate::db::{RepositoryTrait, entity::messages};
-use crate::state::{ThreadEvent, UserMessage};
-use crate::{auth, state};
+const MAX_REACTION_LENGTH: usize = 16;
+
+// Typing indicator is dropped if client does not repeat typing start in time
+const TYPING_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Client has to load rest of the room history over http
+const MAX_RESUME_MESSAGES: u64 = 500;
+
+// Live events are delivered between pages, so event queue does not fill up during replay
+const RESUME_PAGE_SIZE: u64 = 50;
 
 pub struct Controller {
-    pub mutex_state: Arc<Mutex<state::MutexState>>,
     pub service_state: state::ServiceState,
     pub claims: auth::jwt::Claims,
-    pub user_message_receiver: UnboundedReceiver<ThreadEvent>,
+    pub connection_id: ConnectionId,
+    pub user_message_receiver: Receiver<ThreadEvent>,
     pub ws_sender: SplitSink<ws::WebSocket, ws::Message>,
     pub ws_receiver: SplitStream<ws::WebSocket>,
+    pub typing_deadlines: HashMap<i32, Instant>,
+    pub ping_interval: Interval,
+    pub last_activity_at: Instant,
+    pub is_closed: bool,
 }
 
 pub enum ReceivedEvent {
     FromOtherThread(ThreadEvent),
     FromWebsocket(ws::Message),
+    TypingExpired(i32),
+    PingTick,
 }
 
 impl Controller {
     pub async fn get_message(&mut self) -> Option<ReceivedEvent> {
+        if self.is_closed {
+            return None;
+        }
+
+        let next_typing_deadline = self
+            .typing_deadlines
+            .iter()
+            .min_by_key(|(_, deadline)| **deadline)
+            .map(|(room_id, deadline)| (*room_id, *deadline));
+
+        let idle_deadline =
+            self.last_activity_at + Duration::from_secs(*config::WS_IDLE_TIMEOUT_SECS);
+
+        let token_expiry_deadline = Instant::now() + self.claims.expires_in();
+
         tokio::select! {
             input = self.user_message_receiver.recv() => {
                 if let Some(message) = input {
@@ -46,19 +87,79 @@ impl Controller {
             },
             input = self.ws_receiver.next() => {
                 if let Some(Ok(message)) = input {
+                    self.last_activity_at = Instant::now();
                     Some(ReceivedEvent::FromWebsocket(message))
                 } else {
                     None
                 }
+            },
+            room_id = wait_typing_deadline(next_typing_deadline) => {
+                Some(ReceivedEvent::TypingExpired(room_id))
+            },
+            _ = self.ping_interval.tick() => {
+                Some(ReceivedEvent::PingTick)
+            },
+            _ = time::sleep_until(idle_deadline) => {
+                tracing::debug!("Websocket idle timeout, user: {}", self.claims.user_id);
+
+                if let Err(error) = self.close(ws::close_code::AWAY, "Idle timeout").await {
+                    tracing::error!("Websocket close error: {error}");
+                }
+
+                None
+            },
+            _ = time::sleep_until(token_expiry_deadline) => {
+                tracing::debug!("Websocket token expired, user: {}", self.claims.user_id);
+
+                if let Err(error) = self.close_with_error(WsErrorResponse::TokenExpired).await {
+                    tracing::error!("Websocket close error: {error}");
+                }
+
+                None
             }
         }
     }
 
+    /// Sends error as the last response and closes socket, used when token is no longer valid
+    async fn close_with_error(&mut self, error: WsErrorResponse) -> anyhow::Result<()> {
+        self.is_closed = true;
+
+        self.send_ws_response(WsResponse::Err(error)).await?;
+
+        self.close(ws::close_code::POLICY, "Authentication is no longer valid")
+            .await
+    }
+
+    async fn close(&mut self, code: ws::CloseCode, reason: &'static str) -> anyhow::Result<()> {
+        self.is_closed = true;
+
+        let close_frame = ws::CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+
+        self.send_ws_message(ws::Message::Close(Some(close_frame)))
+            .await
+    }
+
+    /// Notifies room members that user stopped typing everywhere, used when socket is closed
+    pub async fn stop_typing_in_all_rooms(&mut self) -> anyhow::Result<()> {
+        let room_ids: Vec<i32> = self.typing_deadlines.keys().copied().collect();
+
+        for room_id in room_ids {
+            self.stop_typing(room_id).await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn process(&mut self, message: ReceivedEvent) -> anyhow::Result<()> {
         match message {
             ReceivedEvent::FromOtherThread(event) => self.process_thread_event(event).await,
-            ReceivedEvent::FromWebsocket(ws_message) => {
-                if let ws::Message::Text(text) = ws_message {
+            ReceivedEvent::TypingExpired(room_id) => self.stop_typing(room_id).await,
+            ReceivedEvent::PingTick => self.send_ws_message(ws::Message::Ping(Bytes::new())).await,
+            ReceivedEvent::FromWebsocket(ws_message) => match ws_message {
+                ws::Message::Text(text) => {
                     let request: Result<WsRequest, serde_json::Error> =
                         serde_json::from_str(text.as_str());
 
@@ -71,13 +172,22 @@ impl Controller {
                                 .await
                         }
                     }
-                } else {
+                }
+                // Pong is queued by websocket library itself, flush sends it right away
+                ws::Message::Ping(_) => self.flush_ws_sender().await,
+                ws::Message::Pong(_) => Ok(()),
+                // Stream ends right after close frame, connection is cleaned up then
+                ws::Message::Close(close_frame) => {
+                    tracing::debug!("Websocket closed by client: {:?}", close_frame);
+                    Ok(())
+                }
+                ws::Message::Binary(_) => {
                     tracing::warn!("Wrong request format");
 
                     self.send_ws_response(WsResponse::Err(WsErrorResponse::WrongFormat))
                         .await
                 }
-            }
+            },
         }
     }
 
@@ -86,8 +196,10 @@ impl Controller {
             ThreadEvent::UserMessage(message) => {
                 let response = WsOkResponse::Message(WsMessageResponse {
                     uuid: message.uuid,
+                    room_id: message.room_id,
                     user_id: message.from_user_id,
                     content: message.content.clone(),
+                    reply_to: message.reply_to,
                     created_at: Utc::now().naive_utc(),
                 });
 
@@ -97,6 +209,59 @@ impl Controller {
                 let response = WsOkResponse::MessagesRead(event);
                 self.send_ws_response(WsResponse::Ok(response)).await
             }
+            ThreadEvent::MessageEdited(event) => {
+                let response = WsOkResponse::MessageEdited(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::MessageDeleted(event) => {
+                let response = WsOkResponse::MessageDeleted(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::ReactionAdded(event) => {
+                let response = WsOkResponse::ReactionAdded(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::ReactionRemoved(event) => {
+                let response = WsOkResponse::ReactionRemoved(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::RoomMemberAdded(event) => {
+                let response = WsOkResponse::RoomMemberAdded(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::RoomMemberRemoved(event) => {
+                let response = WsOkResponse::RoomMemberRemoved(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::RoomMemberRoleChanged(event) => {
+                let response = WsOkResponse::RoomMemberRoleChanged(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::RoomRenamed(event) => {
+                let response = WsOkResponse::RoomRenamed(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::RoomSummary(event) => {
+                let response = WsOkResponse::RoomSummary(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::Typing(event) => {
+                let response = WsOkResponse::Typing(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::Presence(event) => {
+                let response = WsOkResponse::Presence(event);
+                self.send_ws_response(WsResponse::Ok(response)).await
+            }
+            ThreadEvent::SessionRevoked(session_id) => {
+                if session_id != self.claims.session_id {
+                    return Ok(());
+                }
+
+                tracing::debug!("Websocket session revoked: {session_id}");
+
+                self.close_with_error(WsErrorResponse::SessionRevoked).await
+            }
         }
     }
 
@@ -104,18 +269,632 @@ impl Controller {
         match request {
             WsRequest::Message(message) => self.send_message_to_user(message).await,
             WsRequest::MessagesRead(request) => self.mark_messages_read(request).await,
+            WsRequest::EditMessage(request) => self.edit_message(request).await,
+            WsRequest::DeleteMessage(request) => self.delete_message(request).await,
+            WsRequest::AddReaction(request) => self.change_reaction(request, true).await,
+            WsRequest::RemoveReaction(request) => self.change_reaction(request, false).await,
+            WsRequest::TypingStart(request) => self.start_typing(request).await,
+            WsRequest::TypingStop(request) => self.stop_typing(request.room_id).await,
+            WsRequest::Resume(request) => self.resume(request).await,
+            WsRequest::RefreshToken(request) => self.refresh_token(request).await,
         }
     }
 
-    async fn mark_messages_read(
+    async fn refresh_token(&mut self, request: WsRefreshTokenRequest) -> anyhow::Result<()> {
+        let claims = self.service_state.jwt_encoder.decode(request.token);
+
+        let claims = match claims {
+            // Connection stays bound to its session, so session revocation can close it
+            Ok(claims)
+                if claims.user_id == self.claims.user_id
+                    && claims.session_id == self.claims.session_id =>
+            {
+                claims
+            }
+            Ok(claims) => {
+                tracing::error!(
+                    "Refreshed token belongs to other session: {}, {}",
+                    claims.session_id,
+                    self.claims.session_id
+                );
+
+                return self
+                    .send_ws_response(WsResponse::Err(WsErrorResponse::InvalidToken))
+                    .await;
+            }
+            Err(error) => {
+                tracing::error!("Refreshed token decode error: {:?}", error);
+
+                return self
+                    .send_ws_response(WsResponse::Err(WsErrorResponse::InvalidToken))
+                    .await;
+            }
+        };
+
+        let is_session_active = self
+            .service_state
+            .session_repository
+            .is_active(claims.session_id)
+            .await?;
+
+        if !is_session_active {
+            tracing::error!("Refreshed token session is revoked: {}", claims.session_id);
+
+            return self.close_with_error(WsErrorResponse::SessionRevoked).await;
+        }
+
+        self.claims = claims;
+
+        self.send_ws_response(WsResponse::Ok(WsOkResponse::TokenRefreshed))
+            .await
+    }
+
+    /// Replays changes missed while client was offline, live events received meanwhile
+    /// are delivered between replayed pages, client deduplicates them by uuid
+    async fn resume(&mut self, request: WsResumeRequest) -> anyhow::Result<()> {
+        let mut truncated_room_ids = Vec::new();
+
+        for cursor in request.rooms {
+            // Session may be revoked by event delivered between pages
+            if self.is_closed {
+                return Ok(());
+            }
+
+            let room_users = self
+                .service_state
+                .room_repository
+                .get_room_users(cursor.room_id)
+                .await?;
+
+            let user_is_member = room_users
+                .iter()
+                .any(|room_user| room_user.user_id == self.claims.user_id);
+
+            if !user_is_member {
+                tracing::error!(
+                    "User is not member of room: {}, {}",
+                    cursor.room_id,
+                    self.claims.user_id
+                );
+
+                self.send_ws_response(WsResponse::Err(WsErrorResponse::NotMemberOfRoom))
+                    .await?;
+                continue;
+            }
+
+            let changes_cursor = match (cursor.last_seen_at, cursor.last_message_uuid) {
+                (Some(last_seen_at), _) => Some(ChangesCursor {
+                    since: last_seen_at,
+                    last_message_id: None,
+                }),
+                (None, Some(last_message_uuid)) => self
+                    .service_state
+                    .message_repository
+                    .get_message_by_uuid(last_message_uuid)
+                    .await?
+                    .filter(|message| message.room_id == cursor.room_id)
+                    .map(|message| ChangesCursor {
+                        since: message.created_at,
+                        last_message_id: Some(message.id),
+                    }),
+                (None, None) => None,
+            };
+
+            let Some(changes_cursor) = changes_cursor else {
+                tracing::warn!("Wrong resume cursor for room: {}", cursor.room_id);
+
+                self.send_ws_response(WsResponse::Err(WsErrorResponse::WrongFormat))
+                    .await?;
+                continue;
+            };
+
+            let is_truncated = self.replay_messages(cursor.room_id, changes_cursor).await?;
+
+            if self.is_closed {
+                return Ok(());
+            }
+
+            if is_truncated {
+                truncated_room_ids.push(cursor.room_id);
+            }
+
+            self.replay_read_cursors(cursor.room_id, room_users).await?;
+        }
+
+        let response =
+            WsOkResponse::ResumeCompleted(WsResumeCompletedResponse { truncated_room_ids });
+
+        self.send_ws_response(WsResponse::Ok(response)).await
+    }
+
+    /// Replays room changes page by page, returns whether changes are left after the limit
+    async fn replay_messages(
         &mut self,
-        request: WsMarkMessagesReadRequest,
+        room_id: i32,
+        cursor: ChangesCursor,
+    ) -> anyhow::Result<bool> {
+        let mut replayed_count = 0;
+        let mut after_id = 0;
+
+        while replayed_count < MAX_RESUME_MESSAGES {
+            let page_size = RESUME_PAGE_SIZE.min(MAX_RESUME_MESSAGES - replayed_count);
+            let messages = self
+                .service_state
+                .message_repository
+                .get_messages_changed_since(room_id, cursor, after_id, page_size)
+                .await?;
+
+            let is_last_page = (messages.len() as u64) < page_size;
+            replayed_count += messages.len() as u64;
+
+            for message in messages {
+                after_id = message.id;
+                self.replay_message(message, cursor).await?;
+            }
+
+            self.deliver_queued_thread_events().await?;
+
+            if is_last_page || self.is_closed {
+                return Ok(false);
+            }
+        }
+
+        // Limit may be reached exactly by the last change
+        let next_messages = self
+            .service_state
+            .message_repository
+            .get_messages_changed_since(room_id, cursor, after_id, 1)
+            .await?;
+
+        Ok(!next_messages.is_empty())
+    }
+
+    /// Delivers live events queued by other connections without waiting for new ones
+    async fn deliver_queued_thread_events(&mut self) -> anyhow::Result<()> {
+        while let Ok(event) = self.user_message_receiver.try_recv() {
+            self.process_thread_event(event).await?;
+
+            if self.is_closed {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn replay_message(
+        &mut self,
+        message: messages::Model,
+        cursor: ChangesCursor,
+    ) -> anyhow::Result<()> {
+        if let Some(deleted_at) = message.deleted_at {
+            let response = WsOkResponse::MessageDeleted(MessageDeletedEvent {
+                uuid: message.uuid,
+                room_id: message.room_id,
+                deleted_at,
+            });
+
+            return self.send_ws_response(WsResponse::Ok(response)).await;
+        }
+
+        // New message already contains edited content
+        if cursor.is_new(&message) {
+            let response = WsOkResponse::Message(WsMessageResponse {
+                uuid: message.uuid,
+                room_id: message.room_id,
+                user_id: message.user_id,
+                content: message.content,
+                reply_to: message.reply_to,
+                created_at: message.created_at,
+            });
+
+            return self.send_ws_response(WsResponse::Ok(response)).await;
+        }
+
+        let Some(edited_at) = message.edited_at else {
+            return Ok(());
+        };
+
+        let response = WsOkResponse::MessageEdited(MessageEditedEvent {
+            uuid: message.uuid,
+            room_id: message.room_id,
+            content: message.content,
+            edited_at,
+        });
+
+        self.send_ws_response(WsResponse::Ok(response)).await
+    }
+
+    /// Sends read cursor of every other member, everything up to the cursor message is read
+    async fn replay_read_cursors(
+        &mut self,
+        room_id: i32,
+        room_users: Vec<rooms_users::Model>,
+    ) -> anyhow::Result<()> {
+        let read_cursors: Vec<(i32, i32)> = room_users
+            .iter()
+            .filter(|room_user| room_user.user_id != self.claims.user_id)
+            .filter_map(|room_user| {
+                room_user
+                    .last_read_message_id
+                    .map(|message_id| (room_user.user_id, message_id))
+            })
+            .collect();
+
+        let last_read_messages = self
+            .service_state
+            .message_repository
+            .get_messages_by_ids(read_cursors.iter().map(|(_, id)| *id).collect())
+            .await?;
+
+        for (user_id, message_id) in read_cursors {
+            let Some(message) = last_read_messages
+                .iter()
+                .find(|message| message.id == message_id)
+            else {
+                continue;
+            };
+
+            let response = WsOkResponse::ReadCursor(WsReadCursorResponse {
+                room_id,
+                user_id,
+                last_read_message_uuid: message.uuid,
+            });
+
+            self.send_ws_response(WsResponse::Ok(response)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn start_typing(&mut self, request: WsTypingRequest) -> anyhow::Result<()> {
+        // Repeated typing start only extends the deadline, membership was checked on the first
+        if let Some(deadline) = self.typing_deadlines.get_mut(&request.room_id) {
+            *deadline = Instant::now() + TYPING_TIMEOUT;
+
+            return Ok(());
+        }
+
+        let room_users = self
+            .service_state
+            .room_repository
+            .get_users_by_room(request.room_id)
+            .await?;
+
+        let user_is_member = room_users.iter().any(|user| user.id == self.claims.user_id);
+
+        if !user_is_member {
+            tracing::error!(
+                "User is not member of room: {}, {}",
+                request.room_id,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::NotMemberOfRoom))
+                .await;
+        }
+
+        self.typing_deadlines
+            .insert(request.room_id, Instant::now() + TYPING_TIMEOUT);
+
+        self.send_typing_event(request.room_id, room_users, true)
+            .await
+    }
+
+    async fn stop_typing(&mut self, room_id: i32) -> anyhow::Result<()> {
+        if self.typing_deadlines.remove(&room_id).is_none() {
+            return Ok(());
+        }
+
+        let room_users = self
+            .service_state
+            .room_repository
+            .get_users_by_room(room_id)
+            .await?;
+
+        self.send_typing_event(room_id, room_users, false).await
+    }
+
+    async fn send_typing_event(
+        &self,
+        room_id: i32,
+        room_users: Vec<users::Model>,
+        is_typing: bool,
     ) -> anyhow::Result<()> {
+        let thread_event = state::ThreadEvent::Typing(TypingEvent {
+            room_id,
+            user_id: self.claims.user_id,
+            is_typing,
+        });
+
+        for user in room_users {
+            if user.id == self.claims.user_id {
+                continue;
+            }
+
+            self.send_thread_event(user.id, thread_event.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn change_reaction(
+        &mut self,
+        request: WsReactionRequest,
+        is_added: bool,
+    ) -> anyhow::Result<()> {
+        let emoji = request.emoji.trim().to_string();
+        if emoji.is_empty() || emoji.chars().count() > MAX_REACTION_LENGTH {
+            tracing::warn!("Wrong reaction format: {}", request.emoji);
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::WrongFormat))
+                .await;
+        }
+
+        let message = self
+            .service_state
+            .message_repository
+            .get_message_by_uuid(request.message_uuid)
+            .await?;
+
+        let Some(message) = message.filter(|message| message.deleted_at.is_none()) else {
+            tracing::error!("Message not found by uuid: {}", request.message_uuid);
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::MessageNotFound))
+                .await;
+        };
+
+        let room_users = self
+            .service_state
+            .room_repository
+            .get_users_by_room(message.room_id)
+            .await?;
+
+        let user_is_member = room_users.iter().any(|user| user.id == self.claims.user_id);
+
+        if !user_is_member {
+            tracing::error!(
+                "User is not member of room: {}, {}",
+                message.room_id,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::NotMemberOfRoom))
+                .await;
+        }
+
+        let is_changed = if is_added {
+            self.service_state
+                .message_repository
+                .add_reaction(message.id, self.claims.user_id, emoji.clone())
+                .await?
+        } else {
+            self.service_state
+                .message_repository
+                .remove_reaction(message.id, self.claims.user_id, emoji.clone())
+                .await?
+        };
+
+        let event = ReactionEvent {
+            room_id: message.room_id,
+            message_uuid: message.uuid,
+            user_id: self.claims.user_id,
+            emoji,
+        };
+
+        let (thread_event, response) = if is_added {
+            (
+                state::ThreadEvent::ReactionAdded(event.clone()),
+                WsOkResponse::ReactionAdded(event),
+            )
+        } else {
+            (
+                state::ThreadEvent::ReactionRemoved(event.clone()),
+                WsOkResponse::ReactionRemoved(event),
+            )
+        };
+
+        if is_changed {
+            for user in room_users {
+                if user.id == self.claims.user_id {
+                    continue;
+                }
+
+                self.send_thread_event(user.id, thread_event.clone());
+            }
+        }
+
+        self.send_ws_response(WsResponse::Ok(response)).await
+    }
+
+    async fn edit_message(&mut self, request: WsEditMessageRequest) -> anyhow::Result<()> {
+        let message = self
+            .service_state
+            .message_repository
+            .get_message_by_uuid(request.uuid)
+            .await?;
+
+        let Some(message) = message.filter(|message| message.deleted_at.is_none()) else {
+            tracing::error!("Message not found by uuid: {}", request.uuid);
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::MessageNotFound))
+                .await;
+        };
+
+        if message.user_id != self.claims.user_id {
+            tracing::error!(
+                "User is not author of message: {}, {}",
+                request.uuid,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::NotMessageAuthor))
+                .await;
+        }
+
+        let room_users = self
+            .service_state
+            .room_repository
+            .get_users_by_room(message.room_id)
+            .await?;
+
+        let user_is_member = room_users.iter().any(|user| user.id == self.claims.user_id);
+
+        if !user_is_member {
+            tracing::error!(
+                "User is not member of room: {}, {}",
+                message.room_id,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::NotMemberOfRoom))
+                .await;
+        }
+
+        let edited_at = Utc::now().naive_utc();
+        let room_id = message.room_id;
+
+        let mut message_model = message.into_active_model();
+        message_model.content = Set(request.content.clone());
+        message_model.edited_at = Set(Some(edited_at));
+
+        self.service_state
+            .message_repository
+            .update(message_model)
+            .await?;
+
+        let event = MessageEditedEvent {
+            uuid: request.uuid,
+            room_id,
+            content: request.content,
+            edited_at,
+        };
+
+        let thread_event = state::ThreadEvent::MessageEdited(event.clone());
+
+        for user in room_users {
+            if user.id == self.claims.user_id {
+                continue;
+            }
+
+            self.send_thread_event(user.id, thread_event.clone());
+        }
+
+        // Edited message may be the last message shown in room list
+        self.send_room_summaries(room_id).await?;
+
+        self.send_ws_response(WsResponse::Ok(WsOkResponse::MessageEdited(event)))
+            .await
+    }
+
+    async fn delete_message(&mut self, request: WsDeleteMessageRequest) -> anyhow::Result<()> {
+        let message = self
+            .service_state
+            .message_repository
+            .get_message_by_uuid(request.uuid)
+            .await?;
+
+        let Some(message) = message.filter(|message| message.deleted_at.is_none()) else {
+            tracing::error!("Message not found by uuid: {}", request.uuid);
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::MessageNotFound))
+                .await;
+        };
+
+        let room_user = self
+            .service_state
+            .room_repository
+            .get_room_user(message.room_id, self.claims.user_id)
+            .await?;
+
+        let Some(room_user) = room_user else {
+            tracing::error!(
+                "User is not member of room: {}, {}",
+                message.room_id,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::NotMemberOfRoom))
+                .await;
+        };
+
+        let room = self
+            .service_state
+            .room_repository
+            .get_by_id(message.room_id)
+            .await?;
+
+        // Both members of private room are owners, so only group rooms are moderated
+        let is_group_room = room.is_some_and(|room| room.private_room_key.is_none());
+
+        let can_delete = message.user_id == self.claims.user_id
+            || (is_group_room && room_user.role.can_manage_room());
+
+        if !can_delete {
+            tracing::error!(
+                "User is not allowed to delete message: {}, {}",
+                request.uuid,
+                self.claims.user_id
+            );
+
+            return self
+                .send_ws_response(WsResponse::Err(WsErrorResponse::PermissionDenied))
+                .await;
+        }
+
+        let deleted_at = Utc::now().naive_utc();
+        let room_id = message.room_id;
+
+        let mut message_model = message.into_active_model();
+        message_model.content = Set(String::new());
+        message_model.deleted_at = Set(Some(deleted_at));
+
         self.service_state
             .message_repository
-            .mark_messages_read(request.message_uuids.clone())
+            .update(message_model)
             .await?;
 
+        let room_users = self
+            .service_state
+            .room_repository
+            .get_users_by_room(room_id)
+            .await?;
+
+        let event = MessageDeletedEvent {
+            uuid: request.uuid,
+            room_id,
+            deleted_at,
+        };
+
+        let thread_event = state::ThreadEvent::MessageDeleted(event.clone());
+
+        for user in room_users {
+            if user.id == self.claims.user_id {
+                continue;
+            }
+
+            self.send_thread_event(user.id, thread_event.clone());
+        }
+
+        self.send_room_summaries(room_id).await?;
+
+        self.send_ws_response(WsResponse::Ok(WsOkResponse::MessageDeleted(event)))
+            .await
+    }
+
+    async fn mark_messages_read(
+        &mut self,
+        request: WsMarkMessagesReadRequest,
+    ) -> anyhow::Result<()> {
         let room_users = self
             .service_state
             .room_repository
@@ -138,18 +917,32 @@ impl Controller {
                 .await;
         }
 
-        let thread_event = state::ThreadEvent::MessagesRead(request);
+        let read_message_uuids = self
+            .service_state
+            .message_repository
+            .mark_messages_read(request.room_id, self.claims.user_id, request.message_uuids)
+            .await?;
+
+        if read_message_uuids.is_empty() {
+            return Ok(());
+        }
+
+        let thread_event = state::ThreadEvent::MessagesRead(MessagesReadEvent {
+            room_id: request.room_id,
+            user_id: self.claims.user_id,
+            message_uuids: read_message_uuids,
+        });
 
         for user in room_users {
             if user.id == self.claims.user_id {
                 continue;
             }
 
-            self.send_thread_event(user.id, thread_event.clone())
-                .await?;
+            self.send_thread_event(user.id, thread_event.clone());
         }
 
-        Ok(())
+        // Only reader's unread count changes
+        self.send_own_room_summary(request.room_id).await
     }
 
     async fn send_message_to_user(&mut self, request: WsMessageRequest) -> anyhow::Result<()> {
@@ -187,13 +980,38 @@ impl Controller {
                 .await;
         }
 
+        if let Some(reply_to) = request.reply_to {
+            let replied_message = self
+                .service_state
+                .message_repository
+                .get_message_by_uuid(reply_to)
+                .await?;
+
+            // Deleted message is shown as tombstone, so it can't be replied to
+            let is_replyable = replied_message.is_some_and(|message| {
+                message.room_id == request.room_id && message.deleted_at.is_none()
+            });
+
+            if !is_replyable {
+                tracing::error!(
+                    "Replied message not found in room: {}, {}",
+                    reply_to,
+                    request.room_id
+                );
+
+                return self
+                    .send_ws_response(WsResponse::Err(WsErrorResponse::MessageNotFound))
+                    .await;
+            }
+        }
+
         let save_to_db = async {
             let message_model = messages::ActiveModel {
                 uuid: Set(request.uuid.clone()),
                 user_id: Set(self.claims.user_id),
                 room_id: Set(request.room_id),
                 content: Set(request.content.clone()),
-                read: Set(false),
+                reply_to: Set(request.reply_to),
                 created_at: Set(Utc::now().naive_utc()),
                 ..Default::default()
             };
@@ -207,17 +1025,20 @@ impl Controller {
         let send_events = async {
             let thread_event = state::ThreadEvent::UserMessage(UserMessage {
                 uuid: request.uuid,
+                room_id: request.room_id,
                 from_user_id: self.claims.user_id,
                 content: request.content.clone(),
+                reply_to: request.reply_to,
             });
 
             for user in room_users {
                 if user.id == self.claims.user_id {
+                    // Keep sender's other devices in sync
+                    self.send_thread_event_to_other_connections(thread_event.clone());
                     continue;
                 }
 
-                self.send_thread_event(user.id, thread_event.clone())
-                    .await?;
+                self.send_thread_event(user.id, thread_event.clone());
             }
 
             Ok::<(), anyhow::Error>(())
@@ -226,27 +1047,74 @@ impl Controller {
         // TODO: run simultaneously ?
         save_to_db.await?;
         send_events.await?;
+        self.stop_typing(request.room_id).await?;
+        self.send_room_summaries(request.room_id).await?;
 
         self.send_ws_response(WsResponse::Ok(WsOkResponse::MessageReceived(request.uuid)))
             .await
     }
 
-    async fn send_thread_event(&self, user_id: i32, event: ThreadEvent) -> anyhow::Result<()> {
-        let found_user_sender = self
-            .mutex_state
-            .lock()
-            .await
-            .user_message_sender_map
-            .get(&user_id)
-            .cloned();
+    /// Sends every member their own unread count and last message of the room
+    async fn send_room_summaries(&self, room_id: i32) -> anyhow::Result<()> {
+        let room_summaries = self
+            .service_state
+            .room_repository
+            .get_for_room_members(room_id)
+            .await?;
+
+        for room_summary in room_summaries {
+            let thread_event =
+                state::ThreadEvent::RoomSummary(RoomSummaryEvent::from(&room_summary));
 
-        if let Some(user_sender) = found_user_sender {
-            user_sender.send(event).map_err(|err| anyhow!(err))?;
+            self.send_thread_event(room_summary.user_id, thread_event);
         }
 
         Ok(())
     }
 
+    /// Sends room summary to every connection of the user, including this one
+    async fn send_own_room_summary(&self, room_id: i32) -> anyhow::Result<()> {
+        let room_summary = self
+            .service_state
+            .room_repository
+            .get_for_room_member(room_id, self.claims.user_id)
+            .await?;
+
+        let Some(room_summary) = room_summary else {
+            return Ok(());
+        };
+
+        let thread_event = state::ThreadEvent::RoomSummary(RoomSummaryEvent::from(&room_summary));
+
+        self.send_thread_event(self.claims.user_id, thread_event);
+
+        Ok(())
+    }
+
+    /// Recipient disconnecting meanwhile is not an error of this connection, so failure is only
+    /// logged and the rest of recipients still get the event
+    fn send_thread_event(&self, user_id: i32, event: ThreadEvent) {
+        if let Err(error) = self
+            .service_state
+            .connection_registry
+            .send_thread_event(user_id, event)
+        {
+            tracing::error!("Thread event send error, user: {user_id}, error: {error}");
+        }
+    }
+
+    fn send_thread_event_to_other_connections(&self, event: ThreadEvent) {
+        let user_id = self.claims.user_id;
+
+        if let Err(error) = self
+            .service_state
+            .connection_registry
+            .send_thread_event_to_other_connections(user_id, self.connection_id, event)
+        {
+            tracing::error!("Thread event send error, user: {user_id}, error: {error}");
+        }
+    }
+
     async fn send_ws_response(&mut self, response: WsResponse) -> anyhow::Result<()> {
         let serialize_result: Result<String, serde_json::Error> = serde_json::to_string(&response);
 
@@ -261,9 +1129,173 @@ impl Controller {
             }
         };
 
-        self.ws_sender
-            .send(ws::Message::Text(ws_response.into()))
+        self.send_ws_message(ws::Message::Text(ws_response.into()))
+            .await
+    }
+
+    /// Client which stopped reading would block the connection task forever otherwise
+    async fn send_ws_message(&mut self, message: ws::Message) -> anyhow::Result<()> {
+        time::timeout(ws_send_timeout(), self.ws_sender.send(message))
             .await
+            .map_err(|_| anyhow!("Websocket send timed out"))?
             .map_err(|err| anyhow!(err))
     }
+
+    async fn flush_ws_sender(&mut self) -> anyhow::Result<()> {
+        time::timeout(ws_send_timeout(), self.ws_sender.flush())
+            .await
+            .map_err(|_| anyhow!("Websocket flush timed out"))?
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+fn ws_send_timeout() -> Duration {
+    Duration::from_secs(*config::WS_SEND_TIMEOUT_SECS)
+}
+
+async fn wait_typing_deadline(deadline: Option<(i32, Instant)>) -> i32 {
+    match deadline {
+        Some((room_id, deadline)) => {
+            time::sleep_until(deadline).await;
+            room_id
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{
+        Router,
+        extract::{ConnectInfo, State, WebSocketUpgrade},
+        routing::any,
+    };
+    use chrono::TimeDelta;
+    use futures::{SinkExt, StreamExt};
+    use nultr_shared_lib::request::{
+        WsErrorResponse, WsMarkMessagesReadRequest, WsRequest, WsResponse,
+    };
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        time::{self, Duration},
+    };
+    use tokio_tungstenite::{
+        MaybeTlsStream, WebSocketStream,
+        tungstenite::{Message, client::IntoClientRequest, http::header},
+    };
+
+    use crate::{auth, state, test_util, ws::connector};
+
+    type ClientSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Serves websocket endpoint like the server does and connects to it as the given user
+    async fn connect(state: &state::ServiceState, user_id: i32) -> ClientSocket {
+        let app = Router::new()
+            .route(
+                "/ws",
+                any(
+                    |ws: WebSocketUpgrade,
+                     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+                     State(state): State<state::ServiceState>,
+                     claims: auth::jwt::Claims| {
+                        connector::handle(ws, addr, state, claims)
+                    },
+                ),
+            )
+            .with_state(state.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        let (session, _) = test_util::insert_session(state, user_id, TimeDelta::hours(1)).await;
+        let token = state.jwt_encoder.encode(user_id, session.uuid).unwrap();
+
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+
+        let (socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        socket
+    }
+
+    async fn send_request(socket: &mut ClientSocket, request: WsRequest) {
+        let request = serde_json::to_string(&request).unwrap();
+
+        socket.send(Message::text(request)).await.unwrap();
+    }
+
+    async fn next_response(socket: &mut ClientSocket) -> WsResponse {
+        loop {
+            let message = time::timeout(Duration::from_secs(5), socket.next())
+                .await
+                .expect("No response received")
+                .expect("Socket closed")
+                .unwrap();
+
+            if let Message::Text(text) = message {
+                return serde_json::from_str(text.as_str()).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn non_member_cannot_mark_messages_read() {
+        let state = test_util::service_state().await;
+        let author = test_util::insert_user(&state, "author").await;
+        let member = test_util::insert_user(&state, "member").await;
+        let outsider = test_util::insert_user(&state, "outsider").await;
+        let room = test_util::insert_group_room(&state, &[author.id, member.id]).await;
+        let message = test_util::insert_message(&state, room.id, author.id).await;
+
+        let mut socket = connect(&state, outsider.id).await;
+
+        send_request(
+            &mut socket,
+            WsRequest::MessagesRead(WsMarkMessagesReadRequest {
+                room_id: room.id,
+                message_uuids: vec![message.uuid],
+            }),
+        )
+        .await;
+
+        let response = next_response(&mut socket).await;
+
+        assert!(matches!(
+            response,
+            WsResponse::Err(WsErrorResponse::NotMemberOfRoom)
+        ));
+
+        for user_id in [author.id, member.id] {
+            let room_user = state
+                .room_repository
+                .get_room_user(room.id, user_id)
+                .await
+                .unwrap()
+                .expect("Room user is removed");
+
+            assert_eq!(room_user.last_read_message_id, None);
+        }
+
+        let outsider_room_user = state
+            .room_repository
+            .get_room_user(room.id, outsider.id)
+            .await
+            .unwrap();
+
+        assert!(outsider_room_user.is_none());
+    }
 }
@@ -1,2 +1,3 @@
 mod controller;
 pub mod connector;
+mod presence;
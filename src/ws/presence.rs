@@ -0,0 +1,89 @@
+use chrono::{NaiveDateTime, Utc};
+use tokio::sync::mpsc::Sender;
+
+use crate::state::{self, ConnectionId, PresenceEvent, ThreadEvent};
+
+/// Registers user connection, room mates are notified if user came online
+pub async fn connect(
+    service_state: &state::ServiceState,
+    user_id: i32,
+    connection_id: ConnectionId,
+    sender: Sender<ThreadEvent>,
+) {
+    let is_first_connection =
+        service_state
+            .connection_registry
+            .register_connection(user_id, connection_id, sender);
+
+    if is_first_connection {
+        notify_room_mates(service_state, user_id, true, None).await;
+    }
+}
+
+/// Removes user connection, if it was the last one stores last seen time and notifies room mates
+pub async fn disconnect(
+    service_state: &state::ServiceState,
+    user_id: i32,
+    connection_id: ConnectionId,
+) {
+    let is_last_connection = service_state
+        .connection_registry
+        .unregister_connection(user_id, connection_id);
+
+    if !is_last_connection {
+        return;
+    }
+
+    let last_seen_at = Utc::now().naive_utc();
+
+    if let Err(error) = service_state
+        .user_repository
+        .update_last_seen_at(user_id, last_seen_at)
+        .await
+    {
+        tracing::error!("Last seen update error, user: {user_id}, error: {error}");
+    }
+
+    notify_room_mates(service_state, user_id, false, Some(last_seen_at)).await;
+}
+
+async fn notify_room_mates(
+    service_state: &state::ServiceState,
+    user_id: i32,
+    is_online: bool,
+    last_seen_at: Option<NaiveDateTime>,
+) {
+    let room_mate_ids = match service_state
+        .room_repository
+        .get_room_mate_ids(user_id)
+        .await
+    {
+        Ok(room_mate_ids) => room_mate_ids,
+        Err(error) => {
+            tracing::error!("Room mates fetch error, user: {user_id}, error: {error}");
+            return;
+        }
+    };
+
+    // User may have reconnected or disconnected while this task was awaiting, then the other
+    // task sends the current state and this event would arrive after it as a stale one
+    if service_state.connection_registry.is_online(user_id) != is_online {
+        tracing::debug!("Stale presence event skipped, user: {user_id}");
+        return;
+    }
+
+    let event = ThreadEvent::Presence(PresenceEvent {
+        user_id,
+        is_online,
+        last_seen_at,
+    });
+
+    for room_mate_id in room_mate_ids {
+        if let Err(error) = service_state
+            .connection_registry
+            .send_thread_event(room_mate_id, event.clone())
+        {
+            tracing::error!("Thread event send error, user: {room_mate_id}, error: {error}");
+        }
+    }
+}